@@ -0,0 +1,92 @@
+//! Lectura de notebooks comprimidos con gzip o guardados dentro de un
+//! archivo `.zip` / `.tar` / `.tar.gz`.
+//!
+//! Un miembro de un archivo se indica con `archivo.zip!ruta/al/notebook.ipynb`.
+
+use flate2::read::GzDecoder;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Separa la ruta del archivo contenedor del nombre del miembro.
+pub const MEMBER_SEPARATOR: char = '!';
+
+/// Lee los bytes del notebook indicado por `path`, descomprimiéndolo o
+/// extrayéndolo del archivo contenedor si hace falta. Devuelve también el
+/// nombre a partir del cual detectar el formato.
+pub fn read_notebook(path: &str) -> io::Result<(Vec<u8>, String)> {
+    if let Some((archive, member)) = split_member(path) {
+        return Ok((read_member(archive, member)?, member.to_string()));
+    }
+    let bytes = fs::read(path)?;
+    Ok((maybe_gunzip(path, bytes)?, path.to_string()))
+}
+
+/// Divide `archivo.zip!miembro` en sus dos partes si la primera es un archivo contenedor.
+pub fn split_member(path: &str) -> Option<(&str, &str)> {
+    path.match_indices(MEMBER_SEPARATOR)
+        .map(|(i, _)| (&path[..i], &path[i + 1..]))
+        .find(|(archive, _)| is_archive(archive))
+}
+
+/// Lee un miembro de un archivo `.zip`, `.tar`, `.tar.gz` o `.tgz`.
+pub fn read_member(archive: &str, member: &str) -> io::Result<Vec<u8>> {
+    let lower = archive.to_ascii_lowercase();
+    let bytes = if lower.ends_with(".zip") {
+        let mut zip = zip::ZipArchive::new(File::open(archive)?).map_err(io::Error::other)?;
+        let mut file = zip.by_name(member).map_err(|e| match e {
+            zip::result::ZipError::FileNotFound => member_not_found(archive, member),
+            other => io::Error::other(other),
+        })?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        bytes
+    } else {
+        let file = File::open(archive)?;
+        let reader: Box<dyn Read> = if lower.ends_with(".tar") {
+            Box::new(file)
+        } else {
+            Box::new(GzDecoder::new(file))
+        };
+        read_tar_member(tar::Archive::new(reader), member)
+            .and_then(|found| found.ok_or_else(|| member_not_found(archive, member)))?
+    };
+    maybe_gunzip(member, bytes)
+}
+
+fn read_tar_member<R: Read>(mut tar: tar::Archive<R>, member: &str) -> io::Result<Option<Vec<u8>>> {
+    let wanted = Path::new(member.trim_start_matches("./"));
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if path.strip_prefix("./").unwrap_or(&path) == wanted {
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes)?;
+            return Ok(Some(bytes));
+        }
+    }
+    Ok(None)
+}
+
+fn is_archive(path: &str) -> bool {
+    let lower = path.to_ascii_lowercase();
+    [".zip", ".tar", ".tar.gz", ".tgz"]
+        .iter()
+        .any(|ext| lower.ends_with(ext))
+}
+
+fn maybe_gunzip(name: &str, bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+    if !name.to_ascii_lowercase().ends_with(".gz") {
+        return Ok(bytes);
+    }
+    let mut out = Vec::new();
+    GzDecoder::new(bytes.as_slice()).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+fn member_not_found(archive: &str, member: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} not found in {}", member, archive),
+    )
+}
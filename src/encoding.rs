@@ -0,0 +1,33 @@
+//! Decodificación y normalización del texto de un notebook antes de parsearlo.
+//!
+//! Los notebooks editados en Windows suelen traer finales de línea CRLF y a
+//! veces un BOM UTF-8; ambos se eliminan para que todos los formatos vean
+//! el mismo texto.
+
+use crate::error::NotebookError;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Decodifica bytes UTF-8, con o sin BOM. Los finales de línea se normalizan
+/// después, en [`Notebook::parse`](crate::Notebook::parse).
+pub fn decode(bytes: &[u8]) -> Result<String, NotebookError> {
+    let (skipped, content) = match bytes.strip_prefix(UTF8_BOM) {
+        Some(rest) => (UTF8_BOM.len(), rest),
+        None => (0, bytes),
+    };
+    let text = std::str::from_utf8(content).map_err(|e| NotebookError::Decode {
+        offset: skipped + e.valid_up_to(),
+    })?;
+    Ok(text.to_string())
+}
+
+/// Quita el BOM inicial y convierte los finales CRLF y CR en LF.
+pub fn normalize(text: &str) -> String {
+    newlines(text.strip_prefix('\u{feff}').unwrap_or(text))
+}
+
+/// Convierte los finales CRLF y los CR sueltos en LF, como hace el tokenizador
+/// de Python: así los números de línea del `ast` coinciden con `str::lines`.
+pub fn newlines(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
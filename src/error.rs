@@ -0,0 +1,77 @@
+//! Errores al cargar un notebook y su traducción a excepciones de Python.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::{fmt, io};
+
+create_exception!(
+    jupyter_functions_exec,
+    NotebookDecodeError,
+    PyValueError,
+    "El contenido del notebook no es UTF-8 válido."
+);
+create_exception!(
+    jupyter_functions_exec,
+    NotebookParseError,
+    PyValueError,
+    "El contenido no es un notebook válido en el formato indicado."
+);
+
+#[derive(Debug)]
+pub enum NotebookError {
+    /// No se pudo leer el archivo (o el miembro del archivo contenedor).
+    Io { path: String, source: io::Error },
+    /// El contenido no es UTF-8; `offset` es el byte donde falla la decodificación.
+    Decode { offset: usize },
+    /// El JSON del notebook no es válido.
+    Parse(serde_json::Error),
+    /// Nombre de formato no reconocido.
+    UnknownFormat(String),
+}
+
+impl fmt::Display for NotebookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotebookError::Io { path, source } => {
+                write!(f, "Error opening the notebook {}: {}", path, source)
+            }
+            NotebookError::Decode { offset } => write!(
+                f,
+                "Notebook is not valid UTF-8: invalid byte at offset {}",
+                offset
+            ),
+            NotebookError::Parse(e) => write!(f, "Error parsing the notebook: {}", e),
+            NotebookError::UnknownFormat(name) => write!(f, "Unknown notebook format {}", name),
+        }
+    }
+}
+
+impl std::error::Error for NotebookError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NotebookError::Io { source, .. } => Some(source),
+            NotebookError::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for NotebookError {
+    fn from(e: serde_json::Error) -> Self {
+        NotebookError::Parse(e)
+    }
+}
+
+impl From<NotebookError> for PyErr {
+    fn from(e: NotebookError) -> Self {
+        let message = e.to_string();
+        match e {
+            // conserva la subclase de OSError (FileNotFoundError, PermissionError...)
+            NotebookError::Io { source, .. } => io::Error::new(source.kind(), message).into(),
+            NotebookError::Decode { .. } => NotebookDecodeError::new_err(message),
+            NotebookError::Parse(_) => NotebookParseError::new_err(message),
+            NotebookError::UnknownFormat(_) => PyValueError::new_err(message),
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Notebooks exportados por Databricks en formato source (`.py`).
+//!
+//! El archivo empieza con `# Databricks notebook source` y las celdas se
+//! separan con `# COMMAND ----------`. Las celdas de otros lenguajes llevan
+//! cada línea prefijada con `# MAGIC`: `%md` se expone como markdown,
+//! `%python` como código y el resto (`%sql`, `%sh`, `%scala`...) como raw.
+
+use crate::notebook::{Cell, CellType, Notebook};
+
+pub const HEADER: &str = "# Databricks notebook source";
+const SEPARATOR: &str = "# COMMAND ----------";
+const MAGIC: &str = "# MAGIC";
+
+/// Indica si el contenido es un notebook Databricks en formato source.
+pub fn is_databricks(raw: &str) -> bool {
+    raw.lines().next().is_some_and(|l| l.trim_end() == HEADER)
+}
+
+pub fn parse(raw: &str) -> Notebook {
+    let mut cells = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    for line in raw.lines().skip(1) {
+        if line.trim_end() == SEPARATOR {
+            push_cell(&mut cells, &current);
+            current.clear();
+        } else {
+            current.push(line);
+        }
+    }
+    push_cell(&mut cells, &current);
+    Notebook::from_cells(cells)
+}
+
+fn push_cell(cells: &mut Vec<Cell>, lines: &[&str]) {
+    // `# DBTITLE` guarda el título de la celda, no forma parte de su contenido
+    let lines: Vec<&str> = lines
+        .iter()
+        .copied()
+        .filter(|l| !l.starts_with("# DBTITLE"))
+        .collect();
+    let start = lines.iter().position(|l| !l.trim().is_empty());
+    let end = lines.iter().rposition(|l| !l.trim().is_empty());
+    let (Some(start), Some(end)) = (start, end) else {
+        return;
+    };
+    let lines = &lines[start..=end];
+    let is_magic = lines
+        .iter()
+        .all(|l| l.trim().is_empty() || l.starts_with(MAGIC));
+    if !is_magic {
+        cells.push(Cell::new(CellType::Code, lines.join("\n")));
+        return;
+    }
+    let mut body: Vec<&str> = lines.iter().map(|l| unmagic(l)).collect();
+    let command = body[0].split_whitespace().next().unwrap_or("");
+    let cell_type = match command {
+        "%md" | "%md-sandbox" => CellType::Markdown,
+        "%python" => CellType::Code,
+        _ => CellType::Raw,
+    };
+    if cell_type != CellType::Raw {
+        let first = body[0].trim_start();
+        body[0] = first.strip_prefix(command).unwrap_or(first).trim_start();
+        if body[0].is_empty() {
+            body.remove(0);
+        }
+    }
+    cells.push(Cell::new(cell_type, body.join("\n")));
+}
+
+/// Quita el prefijo `# MAGIC` (y el espacio que lo sigue) de una línea.
+fn unmagic(line: &str) -> &str {
+    let rest = line.strip_prefix(MAGIC).unwrap_or(line);
+    rest.strip_prefix(' ').unwrap_or(rest)
+}
@@ -0,0 +1,149 @@
+//! Notebooks de marimo (`.py` con `app = marimo.App()`).
+//!
+//! Cada celda es una función decorada con `@app.cell` cuyas definiciones
+//! viven en su cuerpo. Se toma ese cuerpo sin su `return` final (que solo
+//! exporta nombres a otras celdas) y con un nivel de indentación menos.
+//! El bloque `with app.setup:` y las funciones `@app.function` también se
+//! convierten en celdas de código, y las celdas que solo llaman a `mo.md`
+//! se exponen como markdown.
+
+use crate::notebook::{Cell, CellType, Notebook};
+use regex::Regex;
+
+/// Indica si el contenido es un notebook de marimo.
+pub fn is_marimo(raw: &str) -> bool {
+    raw.lines()
+        .any(|l| l.trim_start().starts_with("import marimo"))
+        && raw.contains("marimo.App(")
+}
+
+pub fn parse(raw: &str) -> Notebook {
+    let md_regex = Regex::new(r#"^mo\.md\(\s*[rfb]*("""|''')"#)
+        .expect("Error making the regex processing the code.");
+    let lines: Vec<&str> = raw.lines().collect();
+    let mut cells = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if is_decorator(line, "@app.cell") {
+            let Some(body_start) = signature_end(&lines, i + 1) else {
+                break;
+            };
+            let end = block_end(&lines, body_start);
+            let body = strip_final_return(dedent(&lines[body_start..end]));
+            if let Some(text) = markdown_text(&body, &md_regex) {
+                cells.push(Cell::new(CellType::Markdown, text));
+            } else if !body.iter().all(|l| l.trim().is_empty()) {
+                cells.push(Cell::new(CellType::Code, body.join("\n")));
+            }
+            i = end;
+        } else if line.starts_with("with app.setup") {
+            let end = block_end(&lines, i + 1);
+            cells.push(Cell::new(
+                CellType::Code,
+                dedent(&lines[i + 1..end]).join("\n"),
+            ));
+            i = end;
+        } else if is_decorator(line, "@app.function") || is_decorator(line, "@app.class_definition")
+        {
+            let Some(body_start) = signature_end(&lines, i + 1) else {
+                break;
+            };
+            let end = block_end(&lines, body_start);
+            cells.push(Cell::new(
+                CellType::Code,
+                trim_blank(&lines[i + 1..end]).join("\n"),
+            ));
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+    Notebook::from_cells(cells)
+}
+
+fn is_decorator(line: &str, name: &str) -> bool {
+    line.strip_prefix(name)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('(') || rest.trim().is_empty())
+}
+
+/// Índice de la primera línea del cuerpo tras la cabecera `def`/`class` que
+/// empieza en `start` (la cabecera puede ocupar varias líneas).
+fn signature_end(lines: &[&str], start: usize) -> Option<usize> {
+    let mut depth = 0i32;
+    for (offset, line) in lines[start..].iter().enumerate() {
+        for c in line.chars() {
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        if depth <= 0 && line.trim_end().ends_with(':') {
+            return Some(start + offset + 1);
+        }
+    }
+    None
+}
+
+/// Índice de la primera línea que ya no pertenece al bloque indentado.
+fn block_end(lines: &[&str], start: usize) -> usize {
+    lines[start..]
+        .iter()
+        .position(|l| !l.trim().is_empty() && !l.starts_with([' ', '\t']))
+        .map_or(lines.len(), |pos| start + pos)
+}
+
+/// Quita la indentación común del cuerpo de una celda.
+fn dedent(lines: &[&str]) -> Vec<String> {
+    let indent = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    trim_blank(lines)
+        .iter()
+        .map(|l| l.get(indent..).unwrap_or("").to_string())
+        .collect()
+}
+
+fn trim_blank<'a>(lines: &'a [&'a str]) -> &'a [&'a str] {
+    let start = lines.iter().position(|l| !l.trim().is_empty());
+    let end = lines.iter().rposition(|l| !l.trim().is_empty());
+    match (start, end) {
+        (Some(start), Some(end)) => &lines[start..=end],
+        _ => &[],
+    }
+}
+
+/// Elimina el `return` con el que la celda exporta sus nombres (puede ocupar
+/// varias líneas, p. ej. `return (\n    a,\n)`).
+fn strip_final_return(mut body: Vec<String>) -> Vec<String> {
+    let last_statement = body
+        .iter()
+        .rposition(|l| !l.is_empty() && !l.starts_with([' ', '\t', ')', ']', '}']));
+    if let Some(pos) = last_statement
+        && (body[pos] == "return"
+            || body[pos].starts_with("return ")
+            || body[pos].starts_with("return("))
+    {
+        body.truncate(pos);
+    }
+    while body.last().is_some_and(|l| l.trim().is_empty()) {
+        body.pop();
+    }
+    body
+}
+
+/// Si la celda solo llama a `mo.md("""...""")`, devuelve el texto markdown.
+fn markdown_text(body: &[String], md_regex: &Regex) -> Option<String> {
+    let code = body.join("\n");
+    let code = code.trim();
+    let caps = md_regex.captures(code)?;
+    let quote = caps.get(1)?.as_str();
+    let rest = &code[caps.get(0)?.end()..];
+    let close = rest.find(quote)?;
+    let tail = rest[close + quote.len()..].trim();
+    (tail == ")").then(|| rest[..close].trim().to_string())
+}
@@ -0,0 +1,121 @@
+//! Documentos Markdown con bloques de código Python (MyST, Quarto, R Markdown).
+//!
+//! Cada bloque delimitado cuyo lenguaje es Python (```` ```python ````,
+//! ```` ```{python} ````, ```` ```{code-cell} ipython3 ````) se convierte en
+//! una celda de código; el resto del texto, incluidos los bloques de otros
+//! lenguajes, queda en celdas markdown. El front matter YAML se descarta.
+
+use crate::notebook::{Cell, CellType, Notebook};
+
+const PYTHON_LANGUAGES: [&str; 5] = ["python", "python3", "py", "ipython", "ipython3"];
+
+pub fn parse(raw: &str) -> Notebook {
+    let lines: Vec<&str> = raw.lines().collect();
+    let mut cells = Vec::new();
+    let mut text: Vec<&str> = Vec::new();
+    let mut i = skip_front_matter(&lines);
+    while i < lines.len() {
+        let line = lines[i];
+        let Some((fence, info)) = opening_fence(line) else {
+            text.push(line);
+            i += 1;
+            continue;
+        };
+        let end = lines[i + 1..]
+            .iter()
+            .position(|l| closes_fence(l, &fence))
+            .map_or(lines.len(), |pos| i + 1 + pos);
+        match python_fence(info) {
+            Some(is_code_cell) => {
+                push_markdown(&mut cells, &text);
+                text.clear();
+                let mut body = &lines[(i + 1).min(end)..end];
+                if is_code_cell {
+                    body = skip_directive_options(body);
+                }
+                cells.push(Cell::new(CellType::Code, body.join("\n")));
+            }
+            None => text.extend_from_slice(&lines[i..(end + 1).min(lines.len())]),
+        }
+        i = end + 1;
+    }
+    push_markdown(&mut cells, &text);
+    Notebook::from_cells(cells)
+}
+
+fn skip_front_matter(lines: &[&str]) -> usize {
+    if lines.first().map(|l| l.trim_end()) != Some("---") {
+        return 0;
+    }
+    lines
+        .iter()
+        .skip(1)
+        .position(|l| l.trim_end() == "---")
+        .map_or(0, |end| end + 2)
+}
+
+/// Devuelve la secuencia de apertura (```` ``` ```` o `~~~`, con su longitud)
+/// y la cadena de información que la sigue.
+fn opening_fence(line: &str) -> Option<(String, &str)> {
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let fence_char = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let fence_len = trimmed.chars().take_while(|c| *c == fence_char).count();
+    if fence_len < 3 {
+        return None;
+    }
+    let fence: String = std::iter::repeat_n(fence_char, fence_len).collect();
+    Some((fence, trimmed[fence_len..].trim()))
+}
+
+fn closes_fence(line: &str, fence: &str) -> bool {
+    let trimmed = line.trim();
+    let fence_char = fence.chars().next().unwrap_or('`');
+    trimmed.len() >= fence.len() && trimmed.chars().all(|c| c == fence_char)
+}
+
+/// Si el bloque es Python devuelve `Some(es_directiva_code_cell)`.
+fn python_fence(info: &str) -> Option<bool> {
+    let (language, is_code_cell) = if let Some(inner) = info.strip_prefix('{') {
+        let close = inner.find('}').unwrap_or(inner.len());
+        let name = inner[..close]
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .next()
+            .unwrap_or("");
+        if name == "code-cell" {
+            let after = inner.get(close + 1..).unwrap_or("").trim();
+            (after.split_whitespace().next().unwrap_or("python"), true)
+        } else {
+            (name, false)
+        }
+    } else {
+        (info.split_whitespace().next().unwrap_or(""), false)
+    };
+    let language = language.to_ascii_lowercase();
+    PYTHON_LANGUAGES
+        .contains(&language.as_str())
+        .then_some(is_code_cell)
+}
+
+/// Quita las opciones de una directiva MyST (`:tags: [...]` o bloque `---`).
+fn skip_directive_options<'a>(body: &'a [&'a str]) -> &'a [&'a str] {
+    if body.first().map(|l| l.trim_end()) == Some("---") {
+        let end = body.iter().skip(1).position(|l| l.trim_end() == "---");
+        return end.map_or(body, |end| &body[end + 2..]);
+    }
+    let options = body
+        .iter()
+        .take_while(|l| l.starts_with(':') && l[1..].contains(':'))
+        .count();
+    &body[options..]
+}
+
+fn push_markdown(cells: &mut Vec<Cell>, lines: &[&str]) {
+    let start = lines.iter().position(|l| !l.trim().is_empty());
+    let end = lines.iter().rposition(|l| !l.trim().is_empty());
+    if let (Some(start), Some(end)) = (start, end) {
+        cells.push(Cell::new(CellType::Markdown, lines[start..=end].join("\n")));
+    }
+}
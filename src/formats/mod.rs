@@ -0,0 +1,65 @@
+//! Formatos de notebook soportados además del `.ipynb`.
+//!
+//! Cada submódulo convierte su formato al modelo [`Notebook`](crate::Notebook)
+//! para que la extracción sea la misma en todos los casos.
+
+pub mod databricks;
+pub mod marimo;
+pub mod markdown;
+pub mod percent;
+
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotebookFormat {
+    /// Notebook JSON de Jupyter (nbformat 3 o 4).
+    Ipynb,
+    /// Script jupytext en formato percent (`# %%`).
+    Percent,
+    /// Documento Markdown con bloques de código (MyST, Quarto, R Markdown).
+    Markdown,
+    /// Notebook Databricks exportado en formato source (`# COMMAND ----------`).
+    Databricks,
+    /// Notebook de marimo (celdas `@app.cell`).
+    Marimo,
+}
+
+impl NotebookFormat {
+    /// Formato del argumento `format` de los constructores. Acepta la
+    /// extensión (`ipynb`, `py`, `md`, `qmd`, `rmd`) o el nombre del formato
+    /// (`json`, `percent`, `py:percent`, `markdown`, `myst`, `databricks`,
+    /// `marimo`), sin distinguir mayúsculas.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "ipynb" | "json" => Some(NotebookFormat::Ipynb),
+            "py" | "percent" | "py:percent" => Some(NotebookFormat::Percent),
+            "md" | "markdown" | "myst" | "qmd" | "rmd" => Some(NotebookFormat::Markdown),
+            "databricks" => Some(NotebookFormat::Databricks),
+            "marimo" => Some(NotebookFormat::Marimo),
+            _ => None,
+        }
+    }
+
+    /// Detecta el formato a partir de la extensión del archivo.
+    /// Un sufijo `.gz` se ignora (`notebook.ipynb.gz` es un `.ipynb`).
+    pub fn from_path(path: &str) -> Self {
+        let lower = path.to_ascii_lowercase();
+        let path = lower.strip_suffix(".gz").unwrap_or(&lower);
+        let extension = Path::new(path).extension().and_then(|e| e.to_str());
+        match extension {
+            Some("py") => NotebookFormat::Percent,
+            Some("md" | "markdown" | "myst" | "qmd" | "rmd") => NotebookFormat::Markdown,
+            _ => NotebookFormat::Ipynb,
+        }
+    }
+
+    /// Afina el formato mirando el contenido: un `.py` puede ser un script
+    /// percent, un notebook Databricks o un notebook de marimo.
+    pub fn detect(self, raw: &str) -> Self {
+        match self {
+            NotebookFormat::Percent if databricks::is_databricks(raw) => NotebookFormat::Databricks,
+            NotebookFormat::Percent if marimo::is_marimo(raw) => NotebookFormat::Marimo,
+            format => format,
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Scripts jupytext en formato percent.
+//!
+//! Cada celda empieza con una línea `# %%`; `# %% [markdown]` (o `[md]`)
+//! marca celdas markdown cuyo texto va comentado con `# `, y `# %% [raw]`
+//! celdas raw. El código anterior al primer marcador forma su propia celda
+//! y la cabecera YAML de jupytext (`# ---` ... `# ---`) se descarta. Las
+//! etiquetas del marcador (`# %% tags=["export"]`) pasan a la celda.
+
+use crate::notebook::{Cell, CellType, Notebook};
+use regex::Regex;
+
+pub fn parse(raw: &str) -> Notebook {
+    let marker =
+        Regex::new(r"^#\s*%%(?:\s+(.*))?$").expect("Error making the regex processing the code.");
+    let lines: Vec<&str> = raw.lines().collect();
+    let mut cells = Vec::new();
+    let mut cell_type = CellType::Code;
+    let mut tags = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    for line in &lines[skip_header(&lines)..] {
+        if let Some(caps) = marker.captures(line) {
+            push_cell(&mut cells, &cell_type, &tags, &current);
+            current.clear();
+            let options = caps.get(1).map_or("", |m| m.as_str());
+            cell_type = marker_cell_type(options);
+            tags = marker_tags(options);
+        } else {
+            current.push(line);
+        }
+    }
+    push_cell(&mut cells, &cell_type, &tags, &current);
+    Notebook::from_cells(cells)
+}
+
+/// Número de líneas que ocupa la cabecera YAML de jupytext, si la hay.
+fn skip_header(lines: &[&str]) -> usize {
+    if lines.first().map(|l| l.trim_end()) != Some("# ---") {
+        return 0;
+    }
+    lines
+        .iter()
+        .skip(1)
+        .position(|l| l.trim_end() == "# ---")
+        .map_or(0, |end| end + 2)
+}
+
+fn marker_cell_type(options: &str) -> CellType {
+    let options = options.trim_start();
+    if options.starts_with("[markdown]") || options.starts_with("[md]") {
+        CellType::Markdown
+    } else if options.starts_with("[raw]") {
+        CellType::Raw
+    } else {
+        CellType::Code
+    }
+}
+
+/// Etiquetas del marcador en la notación de jupytext: `tags=["a", "b"]`.
+fn marker_tags(options: &str) -> Vec<String> {
+    let tags =
+        Regex::new(r"tags=(\[[^\]]*\])").expect("Error making the regex processing the code.");
+    tags.captures(options)
+        .and_then(|caps| serde_json::from_str(&caps[1]).ok())
+        .unwrap_or_default()
+}
+
+fn push_cell(cells: &mut Vec<Cell>, cell_type: &CellType, tags: &[String], lines: &[&str]) {
+    // jupytext separa las celdas con una línea en blanco que no forma parte de ellas
+    let start = lines.iter().position(|l| !l.trim().is_empty());
+    let end = lines.iter().rposition(|l| !l.trim().is_empty());
+    let (Some(start), Some(end)) = (start, end) else {
+        return;
+    };
+    let body: Vec<String> = lines[start..=end]
+        .iter()
+        .map(|line| match cell_type {
+            CellType::Code => line.to_string(),
+            _ => uncomment(line),
+        })
+        .collect();
+    cells.push(Cell::new(cell_type.clone(), body.join("\n")).with_tags(tags.to_vec()));
+}
+
+/// Quita el `# ` con el que jupytext comenta las celdas que no son código.
+fn uncomment(line: &str) -> String {
+    line.strip_prefix("# ")
+        .or_else(|| line.strip_prefix('#'))
+        .unwrap_or(line)
+        .to_string()
+}
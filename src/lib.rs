@@ -1,58 +1,274 @@
 use pyo3::Python;
 use pyo3::prelude::*;
 
-use pyo3::exceptions::PyRuntimeError;
-use pyo3::types::{PyDict, PyModule, PyTuple};
-use regex::Regex;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::sync::PyOnceLock;
+use pyo3::types::{PyDict, PyModule, PyString, PyTuple};
+use std::collections::{BTreeMap, HashSet};
 use std::ffi::CString;
-use std::{collections::HashSet, fs, sync::Once};
+use std::sync::Once;
+
+pub mod archive;
+pub mod encoding;
+pub mod error;
+pub mod formats;
+pub mod magics;
+pub mod nbdev;
+pub mod notebook;
+pub mod options;
+pub mod sections;
+pub mod syntax;
+
+pub use error::{NotebookDecodeError, NotebookError, NotebookParseError};
+pub use formats::NotebookFormat;
+pub use magics::{CellMagic, MagicsMode};
+pub use notebook::{Cell, CellType, Notebook};
+pub use options::{LoadOptions, Resolution};
+pub use sections::MarkdownContext;
+use syntax::{Statement, StatementKind};
 
 #[pyclass]
+#[derive(Default)]
 pub struct JupyterFunctions {
-    pub functions: Vec<(String, String)>,
+    pub functions: Vec<NotebookFunction>,
+    pub classes: Vec<NotebookClass>,
+    pub globals: Vec<NotebookGlobal>,
     pub imports: HashSet<String>,
+    /// Bloques `try`/`if` con imports por posición (celda, línea 1-based); se
+    /// ejecutan en orden de documento junto con las globales.
+    pub guarded_imports: BTreeMap<(usize, usize), String>,
+    /// Versión (mayor, menor) del esquema nbformat leído; `None` si el notebook
+    /// viene de un formato de texto.
+    pub nbformat: Option<(u32, u32)>,
+    /// Módulos virtuales de las celdas `%%writefile archivo.py`: nombre → código.
+    pub modules: BTreeMap<String, String>,
+    /// Celdas de código que no son Python (`%%bash`, `%%sql`...): índice y magic.
+    pub skipped_cells: Vec<(usize, String)>,
+    /// Módulo de destino declarado con `#| default_exp` (nbdev).
+    pub default_exp: Option<String>,
+    /// Etiquetas (`metadata.tags`) de las celdas de código leídas, por índice.
+    pub cell_tags: BTreeMap<usize, Vec<String>>,
+    /// Si es `true`, `exec_function` devuelve las corrutinas sin ejecutarlas
+    /// en lugar de correrlas hasta el final con `asyncio.run`.
+    pub return_awaitables: bool,
+    /// Política para elegir la versión de las funciones redefinidas.
+    pub resolution: Resolution,
+    /// Celda fijada con `use_version` para una función: nombre → índice.
+    pub pinned_versions: BTreeMap<String, usize>,
+}
+
+/// Función extraída del notebook junto con su procedencia.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotebookFunction {
+    pub name: String,
+    /// Código de la definición, decoradores incluidos.
+    pub code: String,
+    /// Expresiones de los decoradores, sin la `@`.
+    pub decorators: Vec<String>,
+    /// Definida con `async def`.
+    pub is_async: bool,
+    /// Definida con una asignación (`f = lambda ...` o `f = functools.partial(...)`).
+    pub is_assignment: bool,
+    /// Markdown anterior y sección del notebook donde está la función.
+    pub context: MarkdownContext,
+    /// `execution_count` de la celda donde está la función.
+    pub execution_count: Option<u32>,
+    /// Nombres que lee la definición al ejecutarse (decoradores, valores por
+    /// defecto, anotaciones).
+    pub definition_names: Vec<String>,
+    pub location: FunctionLocation,
+}
+
+/// Clase de nivel raíz extraída del notebook.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotebookClass {
+    pub name: String,
+    /// Código del bloque `class` completo: decoradores, bases y métodos.
+    pub code: String,
+    /// Expresiones de los decoradores, sin la `@`.
+    pub decorators: Vec<String>,
+    /// Nombres que lee la definición al ejecutarse (decoradores, bases y
+    /// cuerpo de la clase, salvo los cuerpos de los métodos).
+    pub definition_names: Vec<String>,
+    pub location: FunctionLocation,
+}
+
+/// Asignación de nivel raíz (constante o expresión sobre imports y otras
+/// constantes) que las funciones pueden leer como global.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotebookGlobal {
+    /// Nombres asignados (`A, B = 1, 2` asigna dos).
+    pub names: Vec<String>,
+    pub code: String,
+    pub location: FunctionLocation,
+}
+
+/// Celda y líneas (1-based, inclusivas, relativas a la celda) donde se define una función.
+#[pyclass(get_all)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FunctionLocation {
+    pub cell_index: usize,
+    /// `id` de la celda (nbformat 4.5+); `None` si el formato no lo tiene.
+    pub cell_id: Option<String>,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// Una de las definiciones de una función redefinida en varias celdas.
+#[pyclass(get_all)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionVersion {
+    pub location: FunctionLocation,
+    /// `execution_count` de la celda; `None` si no se ejecutó o el formato no lo guarda.
+    pub execution_count: Option<u32>,
+    pub code: String,
+    /// Indica si es la versión que eligió la política de resolución.
+    pub is_active: bool,
+}
+
+/// Función o clase pendiente de escribir en el código generado.
+struct Definition<'a> {
+    name: &'a str,
+    code: &'a str,
+    names: &'a [String],
+    location: &'a FunctionLocation,
+}
+
+impl NotebookFunction {
+    /// Indica si algún decorador de la función es el nombre `name`
+    /// (`@name`, `@name(...)`).
+    pub fn uses_decorator(&self, name: &str) -> bool {
+        uses_decorator(&self.decorators, name)
+    }
+}
+
+impl NotebookClass {
+    /// Indica si algún decorador de la clase es el nombre `name`.
+    pub fn uses_decorator(&self, name: &str) -> bool {
+        uses_decorator(&self.decorators, name)
+    }
+}
+
+fn uses_decorator(decorators: &[String], name: &str) -> bool {
+    decorators.iter().any(|d| {
+        let base = d.split(['(', ' ', '\n']).next().unwrap_or("");
+        base == name
+    })
+}
+
+#[pymethods]
+impl FunctionLocation {
+    fn __repr__(&self) -> String {
+        format!(
+            "FunctionLocation(cell_index={}, cell_id={}, start_line={}, end_line={})",
+            self.cell_index,
+            self.cell_id
+                .as_ref()
+                .map_or(String::from("None"), |id| format!("'{}'", id)),
+            self.start_line,
+            self.end_line
+        )
+    }
 }
 
 #[pymethods]
 impl JupyterFunctions {
     #[new]
-    #[pyo3(signature = (notebook_path))]
-    pub fn new(notebook_path: String) -> Self {
-        let mut functions: Vec<(String, String)> = Vec::new();
-        let mut imports: HashSet<String> = HashSet::new();
-        let raw = fs::read_to_string(notebook_path.clone())
-            .expect(format!("Error opening the notebook {}", notebook_path).as_str());
-        let archive: Vec<String> = raw.lines().map(String::from).collect();
-        let n = archive.len();
-        let mut i = 0;
-        while i < n {
-            if archive[i].contains("\"cell_type\": \"code\"") {
-                // busca la línea inicial (índice absoluto)
-                let indice = archive.iter().enumerate().skip(i).find_map(|(idx, s)| {
-                    if s.contains("\"source\": [") {
-                        Some(idx)
-                    } else {
-                        None
-                    }
-                });
-                if let Some(ini) = indice {
-                    // position devuelve una posición relativa desde ini
-                    if let Some(pos) = archive.iter().skip(ini).position(|s| {
-                        let first_non_whitespace = s.find(|c| c != ' ' && c != '\t');
-                        first_non_whitespace.map_or(false, |pos| s[pos..].starts_with(']'))
-                    }) {
-                        // pos es relativo; tomar pos+1 líneas (incluimos la línea que contiene ']')
-                        let slice_lines: Vec<String> =
-                            archive.iter().skip(ini).take(pos + 1).cloned().collect();
-                        process_code(&mut functions, &mut imports, slice_lines);
-                        // avanzar i al índice absoluto del final
-                        i = ini + pos;
-                    }
-                }
-            }
-            i += 1;
-        }
-        Self { functions, imports }
+    #[pyo3(signature = (notebook_path, **options))]
+    fn py_new(notebook_path: &str, options: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        let options = LoadOptions::from_kwargs(options)?;
+        Ok(Self::open(notebook_path, &options)?)
+    }
+
+    /// Crea la instancia a partir del contenido del notebook ya en memoria
+    #[staticmethod]
+    #[pyo3(signature = (content, format = "ipynb", **options))]
+    pub fn from_string(
+        content: &str,
+        format: &str,
+        options: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        let options = LoadOptions::from_kwargs(options)?;
+        Ok(Self::from_content(
+            content,
+            parse_format(format)?,
+            &options,
+        )?)
+    }
+
+    /// Crea la instancia a partir del contenido codificado en UTF-8
+    #[staticmethod]
+    #[pyo3(signature = (content, format = "ipynb", **options))]
+    pub fn from_bytes(
+        content: &[u8],
+        format: &str,
+        options: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        let options = LoadOptions::from_kwargs(options)?;
+        let raw = encoding::decode(content)?;
+        Ok(Self::from_content(&raw, parse_format(format)?, &options)?)
+    }
+
+    /// Crea la instancia a partir de un dict de Python (incluido un `NotebookNode`)
+    #[staticmethod]
+    #[pyo3(signature = (notebook, **options))]
+    pub fn from_dict(
+        notebook: &Bound<'_, PyAny>,
+        options: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        let options = LoadOptions::from_kwargs(options)?;
+        let json = PyModule::import(notebook.py(), "json")?;
+        let raw: String = json.call_method1("dumps", (notebook,))?.extract()?;
+        Ok(Self::from_content(&raw, NotebookFormat::Ipynb, &options)?)
+    }
+
+    /// Crea la instancia a partir de un miembro de un archivo `.zip` o `.tar(.gz)`
+    #[staticmethod]
+    #[pyo3(signature = (archive_path, member, format = None, **options))]
+    pub fn from_archive(
+        archive_path: &str,
+        member: &str,
+        format: Option<&str>,
+        options: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        let options = LoadOptions::from_kwargs(options)?;
+        let bytes =
+            archive::read_member(archive_path, member).map_err(|source| NotebookError::Io {
+                path: format!("{}{}{}", archive_path, archive::MEMBER_SEPARATOR, member),
+                source,
+            })?;
+        let format = match format {
+            Some(name) => parse_format(name)?,
+            None => NotebookFormat::from_path(member),
+        };
+        let raw = encoding::decode(&bytes)?;
+        Ok(Self::from_content(&raw, format, &options)?)
+    }
+
+    /// Crea la instancia leyendo un objeto tipo archivo (texto o binario).
+    /// Sin `format`, se deduce de su atributo `name` si lo tiene.
+    #[staticmethod]
+    #[pyo3(signature = (file, format = None, **options))]
+    pub fn from_file(
+        file: &Bound<'_, PyAny>,
+        format: Option<&str>,
+        options: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        let options = LoadOptions::from_kwargs(options)?;
+        let format = match format {
+            Some(name) => parse_format(name)?,
+            None => match file.getattr("name").and_then(|n| n.extract::<String>()) {
+                Ok(name) => NotebookFormat::from_path(&name),
+                Err(_) => NotebookFormat::Ipynb,
+            },
+        };
+        let content = file.call_method0("read")?;
+        let raw = if content.is_instance_of::<PyString>() {
+            content.extract::<String>()?
+        } else {
+            encoding::decode(&content.extract::<Vec<u8>>()?)?
+        };
+        Ok(Self::from_content(&raw, format, &options)?)
     }
 
     #[pyo3(signature = (name, /, *args, **kwargs))]
@@ -63,155 +279,734 @@ impl JupyterFunctions {
         args: &Bound<'py, PyTuple>,
         kwargs: Option<&Bound<'py, PyDict>>,
     ) -> PyResult<Py<PyAny>> {
-        if !self.functions.iter().any(|(n, _)| n == name) {
+        if !self.exists_function(name.to_string()) {
             return Err(PyRuntimeError::new_err(format!(
                 "{} doesn't exist in the notebook.",
                 name
             )));
         }
 
-        let main = PyModule::import(py, "__main__")?;
-        let globals = main.dict();
-
-        let mut code = imports_as_lines(self);
-        for (_, func_code) in &self.functions {
-            code += func_code;
-        }
-
-        let c_code = CString::new(code)
-            .map_err(|_| PyRuntimeError::new_err("Código Python contiene byte nulo (\\0)"))?;
-        py.run(&c_code, Some(&globals), None)?;
-
         // Obtiene la función y la ejecuta con *args y **kwargs
-        let func = globals.get_item(name)?.ok_or_else(|| {
-            PyRuntimeError::new_err(format!("{} wasn't defined after executing code.", name))
-        })?;
+        let func = self.define(py, name)?;
 
         let result = func.call(args, kwargs)?;
+        // Las corrutinas se ejecutan hasta el final salvo que se pidan awaitables
+        let inspect = PyModule::import(py, "inspect")?;
+        let is_coroutine: bool = inspect.call_method1("iscoroutine", (&result,))?.extract()?;
+        if is_coroutine && !self.return_awaitables {
+            let asyncio = PyModule::import(py, "asyncio")?;
+            // `asyncio.run` no funciona dentro de un bucle en marcha (Jupyter)
+            if !asyncio.call_method0("_get_running_loop")?.is_none() {
+                result.call_method0("close")?;
+                return Err(PyRuntimeError::new_err(format!(
+                    "{} is a coroutine and an event loop is already running; load the notebook with return_awaitables=True and await the result.",
+                    name
+                )));
+            }
+            return Ok(asyncio.call_method1("run", (result,))?.unbind());
+        }
         Ok(result.unbind())
     }
 
     #[pyo3(signature = (name))]
     pub fn return_function<'py>(&self, py: Python<'py>, name: &str) -> PyResult<Py<PyAny>> {
-        if !self.functions.iter().any(|(n, _)| n == name) {
+        if !self.exists_function(name.to_string()) {
             return Err(PyRuntimeError::new_err(format!(
                 "{} doesn't exist in the notebook.",
                 name
             )));
         }
 
-        let main = PyModule::import(py, "__main__")?;
-        let globals = main.dict();
+        // Obtiene la función y la devuelve sin invocarla
+        Ok(self.define(py, name)?.unbind())
+    }
 
-        let mut code = imports_as_lines(self);
-        for (_, func_code) in &self.functions {
-            code += func_code;
+    /// Devuelve la clase del notebook sin instanciarla
+    #[pyo3(signature = (name))]
+    pub fn return_class<'py>(&self, py: Python<'py>, name: &str) -> PyResult<Py<PyAny>> {
+        if !self.exists_class(name) {
+            return Err(PyRuntimeError::new_err(format!(
+                "{} doesn't exist in the notebook.",
+                name
+            )));
         }
-        let c_code = CString::new(code)
-            .map_err(|_| PyRuntimeError::new_err("Código Python contiene byte nulo (\\0)"))?;
-        py.run(&c_code, Some(&globals), None)?;
 
-        // Obtiene la función y la devuelve sin invocarla
-        let func = globals.get_item(name)?.ok_or_else(|| {
-            PyRuntimeError::new_err(format!("{} wasn't defined after executing code.", name))
-        })?;
+        Ok(self.define(py, name)?.unbind())
+    }
+
+    /// Crea una instancia de una clase del notebook con *args y **kwargs
+    #[pyo3(signature = (name, /, *args, **kwargs))]
+    pub fn instantiate<'py>(
+        &self,
+        py: Python<'py>,
+        name: &str,
+        args: &Bound<'py, PyTuple>,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Py<PyAny>> {
+        if !self.exists_class(name) {
+            return Err(PyRuntimeError::new_err(format!(
+                "{} doesn't exist in the notebook.",
+                name
+            )));
+        }
 
-        Ok(func.unbind())
+        let class = self.define(py, name)?;
+        Ok(class.call(args, kwargs)?.unbind())
     }
 
     pub fn exists_function(&self, name_of_function: String) -> bool {
-        self.functions.iter().any(|(n, _)| n == &name_of_function)
+        self.functions.iter().any(|f| f.name == name_of_function)
     }
 
     pub fn functions_names(&self) -> Vec<String> {
-        let mut names: Vec<String> = self.functions.iter().map(|(n, _)| n.clone()).collect();
+        let mut names: Vec<String> = self.functions.iter().map(|f| f.name.clone()).collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    pub fn classes_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.classes.iter().map(|c| c.name.clone()).collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    pub fn globals_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .globals
+            .iter()
+            .flat_map(|g| g.names.iter().cloned())
+            .collect();
         names.sort();
         names.dedup();
         names
     }
 
+    /// Devuelve la ruta de encabezados de la sección de una función
+    /// (`"Preprocesado > Limpieza"`; vacía si no hay encabezados antes)
+    pub fn function_section(&self, name: String) -> Option<String> {
+        self.definition(&name).map(|f| f.context.section())
+    }
+
+    /// Devuelve el texto de la celda markdown anterior más cercana a una función
+    pub fn function_markdown(&self, name: String) -> Option<String> {
+        self.definition(&name)
+            .and_then(|f| f.context.markdown.clone())
+    }
+
+    /// Devuelve las funciones de una sección, incluidas sus subsecciones
+    pub fn functions_in_section(&self, title: String) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .functions
+            .iter()
+            .filter(|f| self.is_active(f) && f.context.in_section(&title))
+            .map(|f| f.name.clone())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Devuelve las etiquetas de la celda donde se define una función o clase
+    pub fn cell_tags(&self, name: String) -> Option<Vec<String>> {
+        let cell_index = match self.definition(&name) {
+            Some(function) => function.location.cell_index,
+            None => {
+                self.classes
+                    .iter()
+                    .rev()
+                    .find(|c| c.name == name)?
+                    .location
+                    .cell_index
+            }
+        };
+        Some(self.cell_tags.get(&cell_index).cloned().unwrap_or_default())
+    }
+
+    /// Devuelve el módulo declarado con `#| default_exp` (nbdev)
+    pub fn default_exp(&self) -> Option<String> {
+        self.default_exp.clone()
+    }
+
+    /// Nombres de los módulos virtuales creados con `%%writefile archivo.py`
+    pub fn modules_names(&self) -> Vec<String> {
+        self.modules.keys().cloned().collect()
+    }
+
+    /// Devuelve el código de un módulo virtual
+    pub fn module_source(&self, name: String) -> Option<String> {
+        self.modules.get(&name).cloned()
+    }
+
+    /// Devuelve las celdas de código omitidas por no ser Python: `(índice, magic)`
+    pub fn skipped_cells(&self) -> Vec<(usize, String)> {
+        self.skipped_cells.clone()
+    }
+
     pub fn necessary_imports(&self) -> Vec<String> {
         self.imports.iter().cloned().collect()
     }
 
+    /// Devuelve la versión (mayor, menor) del esquema nbformat del notebook,
+    /// o `None` si se leyó de un formato de texto
+    pub fn nbformat_version(&self) -> Option<(u32, u32)> {
+        self.nbformat
+    }
+
     /// Devuelve el código extraído de una función para debug
     pub fn get_function_code(&self, name: String) -> Option<String> {
-        self.functions
-            .iter()
-            .rev()
-            .find(|(n, _)| n == &name)
-            .map(|(_, c)| c.clone())
+        self.definition(&name).map(|f| f.code.clone())
     }
 
     /// Devuelve el código completo que se ejecutaría (imports + función)
     pub fn get_full_code(&self, name: String) -> Option<String> {
+        self.definition(&name).map(|_| self.generated_code())
+    }
+
+    /// Devuelve los decoradores de una función (sin la `@`)
+    pub fn decorators(&self, name: String) -> Option<Vec<String>> {
+        self.definition(&name).map(|f| f.decorators.clone())
+    }
+
+    /// Indica si una función está definida con `async def`
+    pub fn is_async(&self, name: String) -> Option<bool> {
+        self.definition(&name).map(|f| f.is_async)
+    }
+
+    /// Devuelve la celda y las líneas donde se define una función
+    pub fn function_location(&self, name: String) -> Option<FunctionLocation> {
+        self.definition(&name).map(|f| f.location.clone())
+    }
+
+    /// Devuelve todas las definiciones de una función, en orden de documento
+    pub fn function_versions(&self, name: String) -> Vec<FunctionVersion> {
         self.functions
             .iter()
-            .rev()
-            .find(|(n, _)| n == &name)
-            .map(|(_, _)| {
-                let mut code = imports_as_lines(self);
-                for (_, f_code) in &self.functions {
-                    code += f_code;
-                }
-                code
+            .filter(|f| f.name == name)
+            .map(|f| FunctionVersion {
+                location: f.location.clone(),
+                execution_count: f.execution_count,
+                code: f.code.clone(),
+                is_active: self.is_active(f),
             })
+            .collect()
+    }
+
+    /// Usa la definición de una función que está en la celda `cell_index`,
+    /// sea cual sea la política de resolución
+    pub fn use_version(&mut self, name: String, cell_index: usize) -> PyResult<()> {
+        if !self.exists_function(name.clone()) {
+            return Err(PyRuntimeError::new_err(format!(
+                "{} doesn't exist in the notebook.",
+                name
+            )));
+        }
+        if !self
+            .functions
+            .iter()
+            .any(|f| f.name == name && f.location.cell_index == cell_index)
+        {
+            return Err(PyValueError::new_err(format!(
+                "{} isn't defined in cell {}",
+                name, cell_index
+            )));
+        }
+        self.pinned_versions.insert(name, cell_index);
+        Ok(())
     }
 }
 
-pub fn process_code(
-    functions: &mut Vec<(String, String)>,
-    imports: &mut HashSet<String>,
-    raw_lines: Vec<String>,
-) {
-    let code_lines: Vec<String> = raw_lines.into_iter().map(|e| clean_line_json(e)).collect();
-    // Import form - solo imports sin indentación (nivel raíz)
-    let import_regex =
-        Regex::new(r"^(import|from)\s+").expect("Error making the regex processing the code.");
-    let conj_import: HashSet<String> = code_lines
-        .iter()
-        .filter(|&e| import_regex.is_match(e))
-        .map(|e| e.clone() + "\n")
-        .collect();
-    imports.extend(conj_import);
-    // Functions form
-    let func_regex =
-        Regex::new(r"^def\s+(\w+)\s*\(").expect("Error making the regex processing the code.");
-    let mut i = 0;
-    while i < code_lines.len() {
-        let line = code_lines[i].clone();
-        if let Some(mach) = func_regex.captures(line.as_str()) {
-            // captura ahora el nombre correcto en el grupo 1
-            let func_name = mach[1].to_string();
-            let mut func_body = line.clone() + "\n";
-            let mut j = i + 1;
-            'inner: while j < code_lines.len() {
-                let next_line = code_lines[j].clone();
-                // Empty line
-                if next_line.is_empty() {
-                    func_body.push('\n');
-                    j += 1; // mover índice para evitar bucle infinito
+impl JupyterFunctions {
+    /// Carga el notebook de `notebook_path`; hace panic si no se puede leer o parsear.
+    /// Desde Python el constructor lanza la excepción correspondiente.
+    pub fn new(notebook_path: String) -> Self {
+        Self::open(&notebook_path, &LoadOptions::default()).unwrap_or_else(|e| match e {
+            NotebookError::Io { .. } => panic!("{}", e),
+            _ => panic!("{} ({})", e, notebook_path),
+        })
+    }
+
+    /// Carga el notebook de `notebook_path`, que puede estar comprimido con gzip
+    /// o ser un miembro de un archivo (`archivo.zip!notebook.ipynb`).
+    pub fn open(notebook_path: &str, options: &LoadOptions) -> Result<Self, NotebookError> {
+        let (bytes, name) =
+            archive::read_notebook(notebook_path).map_err(|source| NotebookError::Io {
+                path: notebook_path.to_string(),
+                source,
+            })?;
+        let raw = encoding::decode(&bytes)?;
+        Self::from_content(&raw, NotebookFormat::from_path(&name), options)
+    }
+
+    /// Parsea el contenido en el formato indicado y extrae su código.
+    pub fn from_content(
+        raw: &str,
+        format: NotebookFormat,
+        options: &LoadOptions,
+    ) -> Result<Self, NotebookError> {
+        Ok(Self::from_notebook(&Notebook::parse(raw, format)?, options))
+    }
+
+    /// Extrae funciones e imports de las celdas de código de un notebook ya parseado.
+    /// Las magics de IPython se transforman según `options` antes de analizar cada celda,
+    /// y solo se leen las celdas que pasan los filtros de nbdev y de etiquetas.
+    pub fn from_notebook(notebook: &Notebook, options: &LoadOptions) -> Self {
+        let mut functions: Vec<NotebookFunction> = Vec::new();
+        let mut classes: Vec<NotebookClass> = Vec::new();
+        let mut imports: HashSet<String> = HashSet::new();
+        let mut modules: BTreeMap<String, String> = BTreeMap::new();
+        let mut skipped_cells: Vec<(usize, String)> = Vec::new();
+        let mut default_exp: Option<String> = None;
+        let mut cell_tags: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+        let contexts = sections::contexts(notebook);
+        let mut cells: Vec<(usize, &Cell, Vec<String>, Vec<Statement>)> = Vec::new();
+        for (index, cell) in notebook.code_cells() {
+            let mut lines = cell.lines();
+            let directives = nbdev::directives(&lines);
+            if let Some(module) = nbdev::default_exp(&directives) {
+                default_exp = Some(module);
+            }
+            if options.nbdev && !nbdev::is_exported(&directives)
+                || !options.accepts_tags(&cell.metadata.tags)
+            {
+                continue;
+            }
+            if !cell.metadata.tags.is_empty() {
+                cell_tags.insert(index, cell.metadata.tags.clone());
+            }
+            match magics::cell_magic(&lines) {
+                None => {}
+                // La línea de la magic se deja vacía para conservar las ubicaciones
+                Some(CellMagic::Python { line }) => lines[line].clear(),
+                Some(CellMagic::WriteFile { line, path, append }) => {
+                    // Un módulo que ya se puede importar (`json.py`) no se sustituye
+                    match magics::module_name(&path).filter(|name| !is_importable(name)) {
+                        Some(name) => {
+                            let source = modules.entry(name).or_default();
+                            if !append {
+                                source.clear();
+                            }
+                            for code in &lines[line + 1..] {
+                                source.push_str(code);
+                                source.push('\n');
+                            }
+                        }
+                        None => skipped_cells.push((index, String::from("writefile"))),
+                    }
                     continue;
                 }
-                // We look if is tabulated (considera indentación o comentarios)
-                let first_char = next_line.chars().next().unwrap();
-                if matches!(first_char, '\t' | ' ' | '#') {
-                    func_body.push_str(next_line.as_str());
-                    func_body.push('\n');
-                    j += 1;
-                } else {
-                    break 'inner;
+                Some(CellMagic::Foreign(name)) => {
+                    skipped_cells.push((index, name));
+                    continue;
                 }
             }
-            if is_valid_python_code(&func_body) {
-                functions.push((func_name, func_body));
+            let lines = magics::transform(&lines, options.magics);
+            let statements = syntax::parse_cell(&lines);
+            cells.push((index, cell, lines, statements));
+        }
+        for (index, cell, lines, statements) in &cells {
+            for statement in statements {
+                if statement.kind == StatementKind::Import {
+                    imports.insert(statement.code(lines));
+                }
+            }
+            for mut function in functions_from(statements, lines) {
+                function.location.cell_index = *index;
+                function.location.cell_id = cell.id.clone();
+                function.context = contexts[*index].clone();
+                function.execution_count = cell.execution_count;
+                functions.push(function);
+            }
+            for mut class in classes_from(statements, lines) {
+                class.location.cell_index = *index;
+                class.location.cell_id = cell.id.clone();
+                classes.push(class);
+            }
+        }
+        // Las globales y las condiciones de los imports protegidos solo pueden
+        // leer builtins, imports y globales anteriores
+        let mut known = syntax::imported_names(&imports);
+        known.extend(syntax::builtin_names());
+        let mut globals: Vec<NotebookGlobal> = Vec::new();
+        let mut guarded_imports: BTreeMap<(usize, usize), String> = BTreeMap::new();
+        for (index, cell, lines, statements) in &cells {
+            for statement in statements {
+                if let StatementKind::GuardedImport { loaded } = &statement.kind {
+                    if loaded.iter().all(|name| known.contains(name)) {
+                        let code = statement.code(lines);
+                        known.extend(syntax::imported_names(&HashSet::from([code.clone()])));
+                        imports.insert(code.clone());
+                        guarded_imports.insert((*index, statement.start + 1), code);
+                    }
+                    continue;
+                }
+                for mut global in globals_from(std::slice::from_ref(statement), lines, &mut known) {
+                    global.location.cell_index = *index;
+                    global.location.cell_id = cell.id.clone();
+                    globals.push(global);
+                }
             }
+        }
+        Self {
+            functions,
+            classes,
+            globals,
+            imports,
+            guarded_imports,
+            nbformat: notebook.nbformat,
+            modules,
+            skipped_cells,
+            default_exp,
+            cell_tags,
+            resolution: options.resolution,
+            return_awaitables: options.return_awaitables,
+            ..Default::default()
+        }
+    }
+}
+
+impl JupyterFunctions {
+    /// Versión de `name` que se usa: la de la celda fijada con `use_version`
+    /// o, si no hay ninguna, la que elige la política de resolución.
+    fn definition(&self, name: &str) -> Option<&NotebookFunction> {
+        let mut versions = self.functions.iter().filter(|f| f.name == name);
+        if let Some(&cell_index) = self.pinned_versions.get(name)
+            && let Some(function) = versions
+                .clone()
+                .rev()
+                .find(|f| f.location.cell_index == cell_index)
+        {
+            return Some(function);
+        }
+        match self.resolution {
+            Resolution::Last => versions.next_back(),
+            Resolution::ExecutionCount => versions.max_by_key(|f| f.execution_count),
+        }
+    }
 
-            if j > i {
-                i = j - 1;
+    /// Indica si `function` es la versión que se usa de su nombre.
+    fn is_active(&self, function: &NotebookFunction) -> bool {
+        self.definition(&function.name)
+            .is_some_and(|f| std::ptr::eq(f, function))
+    }
+
+    fn exists_class(&self, name: &str) -> bool {
+        self.classes.iter().any(|c| c.name == name)
+    }
+
+    /// Código que se ejecuta: imports, globales, funciones y clases. Las funciones
+    /// y clases van en orden de documento, salvo que su definición (decoradores,
+    /// valores por defecto, anotaciones, bases) lea otra que esté después: esa
+    /// se adelanta. Los cuerpos de las funciones leen los nombres al llamarlas.
+    pub fn generated_code(&self) -> String {
+        // De cada función redefinida solo se ejecuta la versión elegida
+        let functions: Vec<&NotebookFunction> = self
+            .functions
+            .iter()
+            .filter(|f| self.is_active(f))
+            .collect();
+        // Las lambdas y los partials pueden referirse a cualquier `def`: van al final
+        let (assignments, defs): (Vec<&NotebookFunction>, Vec<&NotebookFunction>) =
+            functions.into_iter().partition(|f| f.is_assignment);
+        let mut pending: Vec<Definition<'_>> = defs
+            .into_iter()
+            .map(|f| Definition {
+                name: &f.name,
+                code: &f.code,
+                names: &f.definition_names,
+                location: &f.location,
+            })
+            .chain(self.classes.iter().map(|c| Definition {
+                name: &c.name,
+                code: &c.code,
+                names: &c.definition_names,
+                location: &c.location,
+            }))
+            .collect();
+        pending.sort_by_key(|d| (d.location.cell_index, d.location.start_line));
+        // Los bloques protegidos pueden leer globales (`if USE_FAST: import ujson`)
+        // y las globales, nombres de esos bloques: se intercalan en orden de documento
+        let mut code: String = plain_imports(self)
+            .into_iter()
+            .map(|e| e.clone() + "\n")
+            .collect();
+        let mut ordered: Vec<((usize, usize), &String)> = self
+            .globals
+            .iter()
+            .map(|g| ((g.location.cell_index, g.location.start_line), &g.code))
+            .collect();
+        let mut emitted = HashSet::new();
+        for (position, block) in &self.guarded_imports {
+            if emitted.insert(block) {
+                ordered.push((*position, block));
             }
         }
-        i += 1;
+        ordered.sort_by_key(|(position, _)| *position);
+        for (_, block) in ordered {
+            code += block;
+        }
+        while !pending.is_empty() {
+            // La primera cuya definición no lee ninguna pendiente; si hay un ciclo, la primera
+            let waits = |d: &Definition<'_>| {
+                d.names
+                    .iter()
+                    .any(|n| n != d.name && pending.iter().any(|p| p.name == n))
+            };
+            let next = pending.iter().position(|d| !waits(d)).unwrap_or(0);
+            code += pending.remove(next).code;
+        }
+        for function in assignments {
+            code += &function.code;
+        }
+        code
+    }
+
+    /// Ejecuta el código generado en `__main__` y devuelve el objeto `name`.
+    /// Antes registra los módulos virtuales para que el código pueda importarlos.
+    fn define<'py>(&self, py: Python<'py>, name: &str) -> PyResult<Bound<'py, PyAny>> {
+        install_modules(py, &self.modules)?;
+        let main = PyModule::import(py, "__main__")?;
+        let globals = main.dict();
+
+        let c_code = CString::new(self.generated_code())
+            .map_err(|_| PyRuntimeError::new_err("Código Python contiene byte nulo (\\0)"))?;
+        py.run(&c_code, Some(&globals), None)?;
+
+        globals.get_item(name)?.ok_or_else(|| {
+            PyRuntimeError::new_err(format!("{} wasn't defined after executing code.", name))
+        })
+    }
+}
+
+/// Buscador de `sys.meta_path` para los módulos virtuales: cada módulo se
+/// ejecuta en su primer `import`, así que pueden importarse entre ellos en
+/// cualquier orden. Los paquetes padre (`pkg` para `pkg.utils`) no tienen código.
+/// Solo se descartan de `sys.modules` los módulos que ha cargado él mismo.
+const MODULE_FINDER: &str = r#"
+import importlib.abc
+import importlib.util
+import sys
+
+
+class NotebookModuleFinder(importlib.abc.MetaPathFinder, importlib.abc.Loader):
+    def __init__(self):
+        self.sources = {}
+        self.packages = set()
+
+    def install(self, sources):
+        # Los módulos ya importados se descartan para ejecutar el código actual
+        for name in set(self.sources) | self.packages | set(sources):
+            module = sys.modules.get(name)
+            if module is not None and getattr(module, "__loader__", None) is self:
+                del sys.modules[name]
+        self.sources = dict(sources)
+        self.packages = {
+            name.rsplit(".", depth)[0]
+            for name in self.sources
+            for depth in range(1, name.count(".") + 1)
+        }
+
+    def find_spec(self, fullname, path=None, target=None):
+        if fullname in self.sources:
+            return importlib.util.spec_from_loader(fullname, self)
+        if fullname in self.packages:
+            return importlib.util.spec_from_loader(fullname, self, is_package=True)
+        return None
+
+    def create_module(self, spec):
+        return None
+
+    def exec_module(self, module):
+        source = self.sources.get(module.__name__)
+        if source is None:
+            return
+        module.__file__ = module.__name__.replace(".", "/") + ".py"
+        exec(compile(source, module.__file__, "exec"), module.__dict__)
+"#;
+
+static MODULE_FINDER_INSTANCE: PyOnceLock<Py<PyAny>> = PyOnceLock::new();
+
+/// Registra los módulos virtuales `modules` (nombre → código) para que el
+/// código generado pueda importarlos. El buscador se instala una sola vez,
+/// al final de `sys.meta_path`, así que nunca oculta un módulo real.
+fn install_modules(py: Python<'_>, modules: &BTreeMap<String, String>) -> PyResult<()> {
+    let finder = MODULE_FINDER_INSTANCE.get_or_try_init(py, || -> PyResult<Py<PyAny>> {
+        let namespace = PyDict::new(py);
+        let c_code = CString::new(MODULE_FINDER)
+            .map_err(|_| PyRuntimeError::new_err("Código Python contiene byte nulo (\\0)"))?;
+        py.run(&c_code, Some(&namespace), None)?;
+        let finder = namespace
+            .get_item("NotebookModuleFinder")?
+            .ok_or_else(|| PyRuntimeError::new_err("NotebookModuleFinder wasn't defined."))?
+            .call0()?;
+        PyModule::import(py, "sys")?
+            .getattr("meta_path")?
+            .call_method1("append", (&finder,))?;
+        Ok(finder.unbind())
+    })?;
+    finder
+        .bind(py)
+        .call_method1("install", (modules.clone(),))?;
+    Ok(())
+}
+
+/// Indica si el paquete de nivel raíz de `module` ya se puede importar sin
+/// los módulos virtuales: un módulo interno, congelado o un archivo de
+/// `sys.path` (biblioteca estándar, paquetes instalados).
+fn is_importable(module: &str) -> bool {
+    ensure_python_initialized();
+
+    let top = module.split('.').next().unwrap_or(module);
+    Python::attach(|py| -> PyResult<bool> {
+        let sys = py.import("sys")?;
+        if sys.getattr("builtin_module_names")?.contains(top)? {
+            return Ok(true);
+        }
+        let machinery = py.import("importlib.machinery")?;
+        for finder in ["FrozenImporter", "PathFinder"] {
+            let spec = machinery
+                .getattr(finder)?
+                .call_method1("find_spec", (top,))?;
+            if !spec.is_none() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    })
+    .unwrap_or(false)
+}
+
+fn parse_format(name: &str) -> Result<NotebookFormat, NotebookError> {
+    NotebookFormat::from_name(name).ok_or_else(|| NotebookError::UnknownFormat(name.to_string()))
+}
+
+/// Procesa líneas con el entrecomillado JSON del notebook (`"    \"código\\n\","`).
+pub fn process_code(
+    functions: &mut Vec<(String, String)>,
+    imports: &mut HashSet<String>,
+    raw_lines: Vec<String>,
+) {
+    let code_lines: Vec<String> = raw_lines.into_iter().map(clean_line_json).collect();
+    process_lines(functions, imports, code_lines);
+}
+
+/// Procesa las líneas lógicas de una celda de código (ya sin entrecomillado JSON).
+pub fn process_lines(
+    functions: &mut Vec<(String, String)>,
+    imports: &mut HashSet<String>,
+    code_lines: Vec<String>,
+) {
+    functions.extend(
+        extract_functions(imports, &code_lines)
+            .into_iter()
+            .map(|f| (f.name, f.code)),
+    );
+}
+
+/// Extrae imports y funciones de las líneas de una celda. La ubicación
+/// devuelta solo tiene las líneas; la celda la rellena quien llama.
+pub fn extract_functions(
+    imports: &mut HashSet<String>,
+    code_lines: &[String],
+) -> Vec<NotebookFunction> {
+    let statements = syntax::parse_cell(code_lines);
+    collect_imports(imports, &statements, code_lines);
+    functions_from(&statements, code_lines)
+}
+
+/// Extrae las clases de nivel raíz de las líneas de una celda, con sus
+/// decoradores y métodos. Como en las funciones, la celda la rellena quien llama.
+pub fn extract_classes(code_lines: &[String]) -> Vec<NotebookClass> {
+    classes_from(&syntax::parse_cell(code_lines), code_lines)
+}
+
+/// Extrae las asignaciones de nivel raíz cuyo valor solo usa nombres de
+/// `known` (builtins, imports y globales anteriores). Los nombres asignados
+/// se añaden a `known` para las sentencias siguientes.
+pub fn extract_globals(code_lines: &[String], known: &mut HashSet<String>) -> Vec<NotebookGlobal> {
+    globals_from(&syntax::parse_cell(code_lines), code_lines, known)
+}
+
+/// Añade los imports de nivel raíz, y los `try`/`if` que los protegen.
+fn collect_imports(imports: &mut HashSet<String>, statements: &[Statement], code_lines: &[String]) {
+    for statement in statements {
+        if matches!(
+            statement.kind,
+            StatementKind::Import | StatementKind::GuardedImport { .. }
+        ) {
+            imports.insert(statement.code(code_lines));
+        }
+    }
+}
+
+/// Funciones `def`/`async def` y lambdas o partials asignados a un nombre.
+fn functions_from(statements: &[Statement], code_lines: &[String]) -> Vec<NotebookFunction> {
+    let mut functions = Vec::new();
+    for statement in statements {
+        let (name, is_async, is_assignment) = match &statement.kind {
+            StatementKind::Function { name, is_async } => (name, *is_async, false),
+            StatementKind::CallableAssignment { name } => (name, false, true),
+            _ => continue,
+        };
+        functions.push(NotebookFunction {
+            name: name.clone(),
+            code: statement.code(code_lines),
+            decorators: statement.decorators.clone(),
+            is_async,
+            is_assignment,
+            context: MarkdownContext::default(),
+            execution_count: None,
+            definition_names: statement.definition_names.clone(),
+            location: statement_location(statement),
+        });
+    }
+    functions
+}
+
+fn classes_from(statements: &[Statement], code_lines: &[String]) -> Vec<NotebookClass> {
+    let mut classes = Vec::new();
+    for statement in statements {
+        if let StatementKind::Class { name } = &statement.kind {
+            classes.push(NotebookClass {
+                name: name.clone(),
+                code: statement.code(code_lines),
+                decorators: statement.decorators.clone(),
+                definition_names: statement.definition_names.clone(),
+                location: statement_location(statement),
+            });
+        }
+    }
+    classes
+}
+
+fn globals_from(
+    statements: &[Statement],
+    code_lines: &[String],
+    known: &mut HashSet<String>,
+) -> Vec<NotebookGlobal> {
+    let mut globals = Vec::new();
+    for statement in statements {
+        if let StatementKind::Assignment { targets, loaded } = &statement.kind
+            && loaded.iter().all(|name| known.contains(name))
+        {
+            known.extend(targets.iter().cloned());
+            globals.push(NotebookGlobal {
+                names: targets.clone(),
+                code: statement.code(code_lines),
+                location: statement_location(statement),
+            });
+        }
+    }
+    globals
+}
+
+/// Líneas (1-based) de una sentencia; la celda la rellena quien llama.
+fn statement_location(statement: &Statement) -> FunctionLocation {
+    FunctionLocation {
+        start_line: statement.start + 1,
+        end_line: statement.end + 1,
+        ..Default::default()
     }
 }
 
@@ -226,138 +1021,173 @@ fn ensure_python_initialized() {
 pub fn is_valid_python_code(code: &str) -> bool {
     ensure_python_initialized();
 
-    Python::attach(|py| {
-        match py.import("builtins") {
-            Ok(builtins) => match builtins.getattr("compile") {
-                Ok(compile_fn) => compile_fn.call1((code, "<string>", "exec")).is_ok(),
-                Err(_) => false,
-            },
+    Python::attach(|py| match py.import("builtins") {
+        Ok(builtins) => match builtins.getattr("compile") {
+            Ok(compile_fn) => compile_fn.call1((code, "<string>", "exec")).is_ok(),
             Err(_) => false,
-        }
+        },
+        Err(_) => false,
     })
 }
 
 // Function to clean all the JSON quoting of the notebook.
 pub fn clean_line_json(line: String) -> String {
     let first_non_whitespace = line.find(|c| c != ' ' && c != '\t');
-    if let Some(ind) = first_non_whitespace {
-        if line[ind..].starts_with('"') {
-            let start_quote = ind;
-            // buscar índice del último '"' en la línea
-            return if let Some(end_quote) = line.rfind('"') {
-                // asegurarnos que el end_quote esté después del start_quote
-                if start_quote + 1 >= end_quote {
-                    return String::new();
-                }
-                let slice = &line[start_quote + 1..end_quote];
-                // ahora procesar escapes
-                let line_chars: Vec<char> = slice.chars().collect();
-                let mut content = String::with_capacity(line_chars.len());
-                let mut i = 0;
-                while i < line_chars.len() {
-                    if line_chars[i] == '\\' {
-                        if i < line_chars.len() - 1 {
-                            let next = line_chars[i + 1];
-                            match next {
-                                '"' => {
-                                    content.push('"');
-                                    i += 1;
-                                }
-                                '\\' => {
-                                    content.push('\\');
-                                    i += 1;
-                                }
-                                'n' => {
-                                    content.push('\n');
-                                    i += 1;
-                                }
-                                't' => {
-                                    content.push('\t');
-                                    i += 1;
-                                }
-                                'r' => {
-                                    content.push('\r');
-                                    i += 1;
-                                }
-                                'b' => {
-                                    content.push('\x08'); // backspace
-                                    i += 1;
-                                }
-                                'f' => {
-                                    content.push('\x0C'); // form feed
-                                    i += 1;
-                                }
-                                '/' => {
-                                    content.push('/');
-                                    i += 1;
-                                }
-                                '\'' => {
-                                    content.push('\'');
-                                    i += 1;
-                                }
-                                'u' => {
-                                    // Secuencia Unicode \uXXXX
-                                    if i + 5 < line_chars.len() {
-                                        let hex: String = line_chars[i + 2..i + 6].iter().collect();
-                                        if let Ok(code) = u32::from_str_radix(&hex, 16) {
-                                            if let Some(c) = char::from_u32(code) {
-                                                content.push(c);
-                                                i += 5; // saltar \uXXXX
-                                            } else {
-                                                // Código inválido, mantener literal
-                                                content.push('\\');
-                                                content.push('u');
-                                                i += 1;
-                                            }
+    if let Some(ind) = first_non_whitespace
+        && line[ind..].starts_with('"')
+    {
+        let start_quote = ind;
+        // buscar índice del último '"' en la línea
+        return if let Some(end_quote) = line.rfind('"') {
+            // asegurarnos que el end_quote esté después del start_quote
+            if start_quote + 1 >= end_quote {
+                return String::new();
+            }
+            let slice = &line[start_quote + 1..end_quote];
+            // ahora procesar escapes
+            let line_chars: Vec<char> = slice.chars().collect();
+            let mut content = String::with_capacity(line_chars.len());
+            let mut i = 0;
+            while i < line_chars.len() {
+                if line_chars[i] == '\\' {
+                    if i < line_chars.len() - 1 {
+                        let next = line_chars[i + 1];
+                        match next {
+                            '"' => {
+                                content.push('"');
+                                i += 1;
+                            }
+                            '\\' => {
+                                content.push('\\');
+                                i += 1;
+                            }
+                            'n' => {
+                                content.push('\n');
+                                i += 1;
+                            }
+                            't' => {
+                                content.push('\t');
+                                i += 1;
+                            }
+                            'r' => {
+                                content.push('\r');
+                                i += 1;
+                            }
+                            'b' => {
+                                content.push('\x08'); // backspace
+                                i += 1;
+                            }
+                            'f' => {
+                                content.push('\x0C'); // form feed
+                                i += 1;
+                            }
+                            '/' => {
+                                content.push('/');
+                                i += 1;
+                            }
+                            '\'' => {
+                                content.push('\'');
+                                i += 1;
+                            }
+                            'u' => {
+                                // Secuencia Unicode \uXXXX
+                                if i + 5 < line_chars.len() {
+                                    let hex: String = line_chars[i + 2..i + 6].iter().collect();
+                                    if let Ok(code) = u32::from_str_radix(&hex, 16) {
+                                        if let Some(c) = char::from_u32(code) {
+                                            content.push(c);
+                                            i += 5; // saltar \uXXXX
                                         } else {
-                                            // No es hex válido, mantener literal
+                                            // Código inválido, mantener literal
                                             content.push('\\');
                                             content.push('u');
                                             i += 1;
                                         }
                                     } else {
-                                        // No hay suficientes caracteres, mantener literal
+                                        // No es hex válido, mantener literal
                                         content.push('\\');
                                         content.push('u');
                                         i += 1;
                                     }
-                                }
-                                _ => {
-                                    // Escape no reconocido, mantener ambos caracteres
+                                } else {
+                                    // No hay suficientes caracteres, mantener literal
                                     content.push('\\');
-                                    content.push(next);
+                                    content.push('u');
                                     i += 1;
                                 }
                             }
-                        } else {
-                            content.push('\\');
+                            _ => {
+                                // Escape no reconocido, mantener ambos caracteres
+                                content.push('\\');
+                                content.push(next);
+                                i += 1;
+                            }
                         }
                     } else {
-                        content.push(line_chars[i]);
+                        content.push('\\');
                     }
-                    i += 1;
+                } else {
+                    content.push(line_chars[i]);
                 }
+                i += 1;
+            }
 
-                if content.chars().last().unwrap_or('\n') == '\n' {
-                    content.pop();
-                }
+            if content.chars().last().unwrap_or('\n') == '\n' {
+                content.pop();
+            }
+            // Finales de línea CRLF de notebooks editados en Windows
+            if content.ends_with('\r') {
+                content.pop();
+            }
 
-                content
-            } else {
-                String::new()
-            };
-        }
+            content
+        } else {
+            String::new()
+        };
     }
     String::new()
 }
 
+/// Imports sin posición en el documento, en orden alfabético: los simples y,
+/// detrás, los bloques protegidos añadidos a mano a `imports`.
+fn plain_imports(notebook: &JupyterFunctions) -> Vec<&String> {
+    let guarded: HashSet<&String> = notebook.guarded_imports.values().collect();
+    let mut imports: Vec<&String> = notebook
+        .imports
+        .iter()
+        .filter(|i| !guarded.contains(i))
+        .collect();
+    imports.sort_by_key(|e| (!(e.starts_with("import") || e.starts_with("from")), *e));
+    imports
+}
+
+/// Genera los imports en un orden estable: primero los simples y después los
+/// bloques protegidos (`try`/`if`) en orden de documento.
 pub fn imports_as_lines(notebook: &JupyterFunctions) -> String {
-    notebook.imports.iter().map(|e| e.clone() + "\n").collect()
+    let mut imports = plain_imports(notebook);
+    let mut emitted = HashSet::new();
+    imports.extend(
+        notebook
+            .guarded_imports
+            .values()
+            .filter(|block| emitted.insert(*block)),
+    );
+    imports.into_iter().map(|e| e.clone() + "\n").collect()
 }
 
 #[pymodule]
 fn jupyter_functions_exec(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<JupyterFunctions>()?;
+    m.add_class::<FunctionLocation>()?;
+    m.add_class::<FunctionVersion>()?;
+    m.add(
+        "NotebookDecodeError",
+        m.py().get_type::<NotebookDecodeError>(),
+    )?;
+    m.add(
+        "NotebookParseError",
+        m.py().get_type::<NotebookParseError>(),
+    )?;
     Ok(())
 }
 
@@ -0,0 +1,312 @@
+//! Transformación de la sintaxis de IPython a Python, siguiendo los
+//! transformadores de entrada de IPython (`IPython.core.inputtransformer2`).
+//!
+//! Las líneas con magics (`%time x = f()`), escapes de shell (`!ls`,
+//! `files = !ls`) o ayuda (`obj?`) no son Python válido. Antes de analizar la
+//! celda se traducen a llamadas a `get_ipython()` o se eliminan, según el
+//! modo elegido para el notebook. El número de líneas se conserva para que
+//! las ubicaciones sigan apuntando a la celda original.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+// Se compilan una sola vez: se aplican a cada celda de cada notebook
+// `x = !ls`, `x = %sx ls`
+static ASSIGN_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\s*)([\w.,\[\]\s*]+?)\s*=\s*(!!|!|%)(.*)$")
+        .expect("Error making the regex processing the code.")
+});
+// `!ls`, `!!ls`, `%magic args`, `?obj`
+static ESCAPE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\s*)(!!|!|%|\?\??)(.*)$").expect("Error making the regex processing the code.")
+});
+// `obj?`, `obj??`
+static HELP_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\s*)([\w.]+)(\?\??)\s*$").expect("Error making the regex processing the code.")
+});
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MagicsMode {
+    /// Traduce a `get_ipython().run_line_magic(...)`, `.system(...)`, etc.,
+    /// como hace IPython; el código solo funciona dentro de IPython.
+    #[default]
+    Translate,
+    /// Elimina las líneas (quedan como `pass`, o `x = None` si asignan). Las
+    /// magics de medición (`%time`, `%timeit`, `%prun`) se sustituyen por la
+    /// sentencia que miden.
+    Strip,
+}
+
+impl MagicsMode {
+    /// Modo de la opción `magics`: `"translate"` o `"strip"`, sin distinguir mayúsculas.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "translate" => Some(MagicsMode::Translate),
+            "strip" => Some(MagicsMode::Strip),
+            _ => None,
+        }
+    }
+}
+
+/// Magics que ejecutan la sentencia que reciben como argumento.
+const TIMING_MAGICS: [&str; 3] = ["time", "timeit", "prun"];
+
+/// Transforma las líneas de una celda. Las magics de celda (`%%`) no se tocan.
+pub fn transform(code_lines: &[String], mode: MagicsMode) -> Vec<String> {
+    let (assign_regex, escape_regex, help_regex) = (&*ASSIGN_REGEX, &*ESCAPE_REGEX, &*HELP_REGEX);
+    let mut result = Vec::with_capacity(code_lines.len());
+    let mut in_string: Option<&str> = None;
+    // Una línea dentro de paréntesis abiertos o tras `\` continúa una sentencia
+    // de Python (`% b` puede ser un módulo), así que no es un escape
+    let mut depth = 0i32;
+    let mut continued = false;
+    let mut i = 0;
+    while i < code_lines.len() {
+        let line = &code_lines[i];
+        let inside_statement = in_string.is_some() || depth > 0 || continued;
+        let (open, balance, continues) = scan_line(line, in_string);
+        in_string = open;
+        depth = (depth + balance).max(0);
+        continued = continues;
+        if inside_statement || line.trim_start().starts_with("%%") {
+            result.push(line.clone());
+            i += 1;
+            continue;
+        }
+
+        // Una línea con escape puede continuar con `\` en las siguientes
+        let mut end = i;
+        let mut joined = line.clone();
+        let is_escape =
+            escape_regex.is_match(line) || assign_regex.is_match(line) || help_regex.is_match(line);
+        if is_escape {
+            while joined.ends_with('\\') && end + 1 < code_lines.len() {
+                joined.pop();
+                end += 1;
+                joined.push_str(code_lines[end].trim_start());
+            }
+        }
+
+        let transformed = if let Some(caps) = help_regex.captures(&joined) {
+            let magic = if &caps[3] == "??" { "pinfo2" } else { "pinfo" };
+            Some(line_magic(&caps[1], "", magic, &caps[2], mode))
+        } else if let Some(caps) = assign_regex.captures(&joined) {
+            let lhs = format!("{} = ", &caps[2]);
+            Some(escape(&caps[1], &lhs, &caps[3], &caps[4], mode))
+        } else {
+            escape_regex
+                .captures(&joined)
+                .map(|caps| escape(&caps[1], "", &caps[2], &caps[3], mode))
+        };
+
+        match transformed {
+            Some(code) => {
+                result.push(code);
+                depth = 0;
+                continued = false;
+                // Las líneas de continuación quedan vacías
+                result.extend((i..end).map(|_| String::new()));
+            }
+            None => result.push(line.clone()),
+        }
+        i = end + 1;
+    }
+    result
+}
+
+/// Traduce un escape (`!`, `!!`, `%`, `?`) con su asignación opcional `lhs`.
+fn escape(indent: &str, lhs: &str, kind: &str, content: &str, mode: MagicsMode) -> String {
+    match kind {
+        "!" if lhs.is_empty() => call(indent, lhs, "system", &[content], mode),
+        "!" | "!!" => call(indent, lhs, "getoutput", &[content], mode),
+        "%" => {
+            let (name, args) = content.split_once(' ').unwrap_or((content, ""));
+            line_magic(indent, lhs, name, args, mode)
+        }
+        _ => {
+            let magic = if kind == "??" { "pinfo2" } else { "pinfo" };
+            line_magic(indent, lhs, magic, content.trim(), mode)
+        }
+    }
+}
+
+fn line_magic(indent: &str, lhs: &str, name: &str, args: &str, mode: MagicsMode) -> String {
+    if mode == MagicsMode::Strip && lhs.is_empty() && TIMING_MAGICS.contains(&name) {
+        return format!("{}{}", indent, timed_statement(args));
+    }
+    call(indent, lhs, "run_line_magic", &[name, args], mode)
+}
+
+/// Sentencia que recibe una magic de medición, sin sus opciones (`-n 10 -r 3`).
+fn timed_statement(args: &str) -> &str {
+    let mut rest = args.trim_start();
+    while rest.starts_with('-') {
+        let (option, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        rest = tail.trim_start();
+        // Opciones cuyo valor va en el siguiente argumento
+        if matches!(option, "-n" | "-r" | "-p" | "-l" | "-s" | "-T" | "-D") {
+            rest = rest.split_once(char::is_whitespace).map_or("", |(_, t)| t);
+            rest = rest.trim_start();
+        }
+    }
+    rest
+}
+
+fn call(indent: &str, lhs: &str, method: &str, args: &[&str], mode: MagicsMode) -> String {
+    match mode {
+        MagicsMode::Translate => {
+            let args: Vec<String> = args.iter().map(|a| python_string(a)).collect();
+            format!(
+                "{}{}get_ipython().{}({})",
+                indent,
+                lhs,
+                method,
+                args.join(", ")
+            )
+        }
+        MagicsMode::Strip if lhs.is_empty() => format!("{}pass", indent),
+        MagicsMode::Strip => format!("{}{}None", indent, lhs),
+    }
+}
+
+/// Literal de string de Python, con las mismas comillas que usa `repr`.
+fn python_string(text: &str) -> String {
+    let quote = if text.contains('\'') && !text.contains('"') {
+        '"'
+    } else {
+        '\''
+    };
+    let mut literal = String::from(quote);
+    for c in text.chars() {
+        match c {
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            c if c == quote => {
+                literal.push('\\');
+                literal.push(c);
+            }
+            c => literal.push(c),
+        }
+    }
+    literal.push(quote);
+    literal
+}
+
+/// Recorre una línea como lo haría el tokenizador de Python, sin mirar el
+/// contenido de los strings ni de los comentarios. Devuelve el delimitador del
+/// string de triple comilla que sigue abierto al final (o `None`), el balance
+/// de paréntesis del código y si la línea continúa con `\`.
+fn scan_line<'a>(line: &str, mut open: Option<&'a str>) -> (Option<&'a str>, i32, bool) {
+    const TRIPLE_QUOTES: [&str; 2] = [r#"""""#, "'''"];
+    let mut balance = 0;
+    let mut quote: Option<char> = None;
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        if let Some(delimiter) = open {
+            match rest.find(delimiter) {
+                Some(pos) => {
+                    rest = &rest[pos + 3..];
+                    open = None;
+                }
+                None => return (open, balance, false),
+            }
+            continue;
+        }
+        let width = c.len_utf8();
+        match (quote, c) {
+            // Un `\` dentro de un string escapa el carácter siguiente, también en los raw
+            (Some(_), '\\') => {
+                let next = rest[width..].chars().next().map_or(0, char::len_utf8);
+                if next == 0 {
+                    return (None, balance, true);
+                }
+                rest = &rest[width + next..];
+                continue;
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '#') => break,
+            (None, '"' | '\'') => match TRIPLE_QUOTES.into_iter().find(|d| rest.starts_with(d)) {
+                Some(delimiter) => {
+                    open = Some(delimiter);
+                    rest = &rest[3..];
+                    continue;
+                }
+                None => quote = Some(c),
+            },
+            (None, '(' | '[' | '{') => balance += 1,
+            (None, ')' | ']' | '}') => balance -= 1,
+            (None, _) => {}
+        }
+        rest = &rest[width..];
+    }
+    let code = &line[..line.len() - rest.len()];
+    let continued = quote.is_none() && rest.is_empty() && code.trim_end().ends_with('\\');
+    (open, balance, continued)
+}
+
+/// Magics de celda cuyo cuerpo es Python y se ejecuta tal cual.
+const PYTHON_CELL_MAGICS: [&str; 4] = ["capture", "time", "timeit", "prun"];
+
+/// Magic de celda (`%%nombre args` en la primera línea con contenido).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CellMagic {
+    /// El cuerpo es Python (`%%capture`, `%%time`...): se extrae sin la magic.
+    Python { line: usize },
+    /// `%%writefile archivo`: el cuerpo es el contenido del archivo.
+    WriteFile {
+        line: usize,
+        path: String,
+        append: bool,
+    },
+    /// Otro lenguaje (`%%bash`, `%%sql`, `%%html`...) o magic desconocida.
+    Foreign(String),
+}
+
+/// Detecta la magic de celda; `None` si la celda es Python normal.
+pub fn cell_magic(code_lines: &[String]) -> Option<CellMagic> {
+    let (line, first) = code_lines
+        .iter()
+        .enumerate()
+        .find(|(_, l)| !l.trim().is_empty())?;
+    let content = first.trim().strip_prefix("%%")?;
+    let (name, args) = content
+        .split_once(char::is_whitespace)
+        .unwrap_or((content, ""));
+    if PYTHON_CELL_MAGICS.contains(&name) {
+        return Some(CellMagic::Python { line });
+    }
+    if name == "writefile" {
+        let mut append = false;
+        let mut path = "";
+        for arg in args.split_whitespace() {
+            match arg {
+                "-a" | "--append" => append = true,
+                arg => path = arg,
+            }
+        }
+        if !path.is_empty() {
+            return Some(CellMagic::WriteFile {
+                line,
+                path: path.trim_matches(['"', '\'']).to_string(),
+                append,
+            });
+        }
+    }
+    Some(CellMagic::Foreign(name.to_string()))
+}
+
+/// Nombre de módulo de un archivo `.py` (`pkg/utils.py` → `pkg.utils`).
+pub fn module_name(path: &str) -> Option<String> {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    let stem = path.strip_suffix(".py")?;
+    let parts: Vec<&str> = stem.split(['/', '\\']).collect();
+    let valid = parts.iter().all(|p| {
+        p.chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+            && p.chars().all(|c| c.is_alphanumeric() || c == '_')
+    });
+    valid.then(|| parts.join("."))
+}
@@ -0,0 +1,43 @@
+//! Directivas de nbdev (`#| export`, `#| hide`, `#| default_exp modulo`).
+//!
+//! Las directivas son comentarios `#|` al principio de la celda. En el modo
+//! nbdev solo se extrae el código de las celdas marcadas para exportar, así
+//! que las celdas exploratorias no llegan al código que se ejecuta.
+
+/// Directivas que marcan una celda para exportar.
+const EXPORT_DIRECTIVES: [&str; 3] = ["export", "exporti", "exports"];
+
+/// Directiva de una celda: nombre y argumentos (`default_exp core` → `("default_exp", "core")`).
+pub type Directive = (String, String);
+
+/// Directivas `#|` de las primeras líneas de la celda.
+pub fn directives(code_lines: &[String]) -> Vec<Directive> {
+    code_lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map_while(|l| l.trim_start().strip_prefix("#|"))
+        .filter_map(|content| {
+            let content = content.trim();
+            let (name, args) = content
+                .split_once(char::is_whitespace)
+                .unwrap_or((content, ""));
+            let name = name.trim_end_matches(':');
+            (!name.is_empty()).then(|| (name.to_string(), args.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Indica si la celda se exporta (`#| export`, `#| exporti`, `#| exports`).
+pub fn is_exported(directives: &[Directive]) -> bool {
+    directives
+        .iter()
+        .any(|(name, _)| EXPORT_DIRECTIVES.contains(&name.as_str()))
+}
+
+/// Módulo de destino de `#| default_exp`, si la celda lo declara.
+pub fn default_exp(directives: &[Directive]) -> Option<String> {
+    directives
+        .iter()
+        .find(|(name, args)| name == "default_exp" && !args.is_empty())
+        .map(|(_, args)| args.clone())
+}
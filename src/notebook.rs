@@ -0,0 +1,183 @@
+//! Modelo estructurado de un notebook de Jupyter (nbformat 3.x y 4.x).
+//!
+//! El notebook se deserializa como JSON real, así que el formato del archivo
+//! (minificado, orden de claves, indentación) no afecta a la extracción.
+//! Los notebooks v3 (`worksheets[].cells[].input`) se normalizan al mismo
+//! modelo que los v4.
+
+use crate::encoding;
+use crate::error::NotebookError;
+use crate::formats::{NotebookFormat, databricks, marimo, markdown, percent};
+use serde::{Deserialize, Deserializer};
+
+#[derive(Debug, Clone)]
+pub struct Notebook {
+    /// Versión (mayor, menor) del esquema nbformat; `None` en los formatos de
+    /// texto (percent, markdown, Databricks, marimo), que no tienen esquema.
+    pub nbformat: Option<(u32, u32)>,
+    pub cells: Vec<Cell>,
+}
+
+/// Forma del JSON tal y como viene en el archivo, antes de normalizar.
+#[derive(Deserialize)]
+struct RawNotebook {
+    nbformat: u32,
+    #[serde(default)]
+    nbformat_minor: u32,
+    #[serde(default)]
+    cells: Vec<Cell>,
+    #[serde(default)]
+    worksheets: Vec<Worksheet>,
+}
+
+/// Hoja de un notebook v3; las celdas viven aquí en lugar de en `cells`.
+#[derive(Deserialize)]
+struct Worksheet {
+    #[serde(default)]
+    cells: Vec<Cell>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CellType {
+    Code,
+    Markdown,
+    Raw,
+    /// Celda de encabezado de nbformat 3; se convierte a markdown al normalizar.
+    Heading,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Cell {
+    pub cell_type: CellType,
+    /// En nbformat 3 las celdas de código guardan el código en `input`.
+    #[serde(default, alias = "input", deserialize_with = "multiline_string")]
+    pub source: Vec<String>,
+    /// Identificador de la celda (nbformat 4.5+).
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub metadata: CellMetadata,
+    /// Contador de ejecución de la celda; en nbformat 3 es `prompt_number`.
+    #[serde(default, alias = "prompt_number")]
+    pub execution_count: Option<u32>,
+    /// Nivel del encabezado en las celdas `heading` de nbformat 3.
+    #[serde(default)]
+    level: Option<usize>,
+}
+
+/// Metadatos de la celda que usa la extracción.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CellMetadata {
+    /// Etiquetas de la celda (`metadata.tags`), p. ej. `export` o `scratch`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// `multiline_string` de nbformat: un único string o una lista de strings.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MultilineString {
+    Single(String),
+    Lines(Vec<String>),
+}
+
+fn multiline_string<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match MultilineString::deserialize(deserializer)? {
+        MultilineString::Single(text) => vec![text],
+        MultilineString::Lines(lines) => lines,
+    })
+}
+
+impl Notebook {
+    /// Parsea el contenido de un notebook en el formato indicado.
+    /// El texto se normaliza antes (BOM y finales de línea CRLF).
+    pub fn parse(raw: &str, format: NotebookFormat) -> Result<Self, NotebookError> {
+        let normalized = encoding::normalize(raw);
+        let raw = normalized.as_str();
+        match format.detect(raw) {
+            NotebookFormat::Ipynb => Ok(Self::from_json(raw)?),
+            NotebookFormat::Percent => Ok(percent::parse(raw)),
+            NotebookFormat::Markdown => Ok(markdown::parse(raw)),
+            NotebookFormat::Databricks => Ok(databricks::parse(raw)),
+            NotebookFormat::Marimo => Ok(marimo::parse(raw)),
+        }
+    }
+
+    /// Parsea el contenido JSON de un `.ipynb`.
+    pub fn from_json(raw: &str) -> Result<Self, serde_json::Error> {
+        let raw: RawNotebook = serde_json::from_str(raw)?;
+        let cells = if raw.nbformat < 4 {
+            raw.worksheets
+                .into_iter()
+                .flat_map(|w| w.cells)
+                .map(Cell::heading_to_markdown)
+                .collect()
+        } else {
+            raw.cells
+        };
+        Ok(Self {
+            nbformat: Some((raw.nbformat, raw.nbformat_minor)),
+            cells,
+        })
+    }
+
+    /// Notebook de un formato de texto, sin versión de nbformat.
+    pub fn from_cells(cells: Vec<Cell>) -> Self {
+        Self {
+            nbformat: None,
+            cells,
+        }
+    }
+
+    /// Celdas de código en orden de documento, con su índice en el notebook.
+    pub fn code_cells(&self) -> impl Iterator<Item = (usize, &Cell)> {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.cell_type == CellType::Code)
+    }
+}
+
+impl Cell {
+    /// Crea una celda a partir de su texto completo (para formatos que no son JSON).
+    pub fn new(cell_type: CellType, text: String) -> Self {
+        Self {
+            cell_type,
+            source: vec![text],
+            id: None,
+            metadata: CellMetadata::default(),
+            execution_count: None,
+            level: None,
+        }
+    }
+
+    /// Añade etiquetas a la celda (para formatos que no son JSON).
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.metadata.tags = tags;
+        self
+    }
+
+    /// Devuelve las líneas lógicas del source, sin saltos de línea.
+    pub fn lines(&self) -> Vec<String> {
+        encoding::newlines(&self.source.concat())
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    /// Convierte una celda `heading` de v3 en markdown con `#` según su nivel.
+    fn heading_to_markdown(mut self) -> Self {
+        if self.cell_type == CellType::Heading {
+            let prefix = "#".repeat(self.level.unwrap_or(1));
+            self.source.insert(0, format!("{} ", prefix));
+            self.cell_type = CellType::Markdown;
+        }
+        self
+    }
+}
@@ -0,0 +1,86 @@
+//! Opciones de carga de un notebook, comunes a todos los constructores.
+
+use crate::magics::MagicsMode;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+/// Cómo se elige la versión de una función definida en varias celdas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Resolution {
+    /// La última definición en orden de documento.
+    #[default]
+    Last,
+    /// La definición de la celda con mayor `execution_count`; las celdas sin
+    /// ejecutar cuentan como las más antiguas y los empates los gana la última.
+    ExecutionCount,
+}
+
+impl Resolution {
+    /// Política de la opción `resolution`: `"last"` o `"execution_count"`,
+    /// sin distinguir mayúsculas.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "last" => Some(Resolution::Last),
+            "execution_count" => Some(Resolution::ExecutionCount),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadOptions {
+    /// Qué hacer con las magics y los escapes de shell de IPython.
+    pub magics: MagicsMode,
+    /// Modo nbdev: solo se extraen las celdas marcadas con `#| export`.
+    pub nbdev: bool,
+    /// Si no está vacía, solo se leen las celdas con alguna de estas etiquetas.
+    pub include_tags: Vec<String>,
+    /// Las celdas con alguna de estas etiquetas no se leen.
+    pub exclude_tags: Vec<String>,
+    /// Versión que se usa de las funciones redefinidas.
+    pub resolution: Resolution,
+    /// `exec_function` devuelve las corrutinas sin ejecutarlas en lugar de
+    /// correrlas hasta el final con `asyncio.run`.
+    pub return_awaitables: bool,
+}
+
+impl LoadOptions {
+    /// Lee los `**options` de los constructores de Python.
+    pub fn from_kwargs(options: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        let mut result = Self::default();
+        let Some(options) = options else {
+            return Ok(result);
+        };
+        for (key, value) in options.iter() {
+            let key: String = key.extract()?;
+            match key.as_str() {
+                "magics" => {
+                    let name: String = value.extract()?;
+                    result.magics = MagicsMode::from_name(&name).ok_or_else(|| {
+                        PyValueError::new_err(format!("Unknown magics mode {}", name))
+                    })?;
+                }
+                "nbdev" => result.nbdev = value.extract()?,
+                "include_tags" => result.include_tags = value.extract()?,
+                "exclude_tags" => result.exclude_tags = value.extract()?,
+                "return_awaitables" => result.return_awaitables = value.extract()?,
+                "resolution" => {
+                    let name: String = value.extract()?;
+                    result.resolution = Resolution::from_name(&name).ok_or_else(|| {
+                        PyValueError::new_err(format!("Unknown resolution policy {}", name))
+                    })?;
+                }
+                _ => return Err(PyTypeError::new_err(format!("Unexpected option {}", key))),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Indica si una celda con estas etiquetas pasa los filtros de etiquetas.
+    pub fn accepts_tags(&self, tags: &[String]) -> bool {
+        let included =
+            self.include_tags.is_empty() || tags.iter().any(|tag| self.include_tags.contains(tag));
+        included && !tags.iter().any(|tag| self.exclude_tags.contains(tag))
+    }
+}
@@ -0,0 +1,87 @@
+//! Contexto markdown de las celdas de código: la celda markdown anterior más
+//! cercana y la ruta de encabezados en la que está (`Preprocesado > Limpieza`).
+
+use crate::notebook::{CellType, Notebook};
+use regex::Regex;
+use std::sync::LazyLock;
+
+// `## Título`, con los `#` de cierre opcionales
+static HEADING_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^ {0,3}(#{1,6})(?:[ \t]+(.*?))?(?:[ \t]+#+)?[ \t]*$")
+        .expect("Error making the regex processing the code.")
+});
+
+/// Separador de la ruta de encabezados en la API de Python.
+pub const SECTION_SEPARATOR: &str = " > ";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MarkdownContext {
+    /// Texto de la celda markdown anterior más cercana.
+    pub markdown: Option<String>,
+    /// Encabezados que contienen la celda, del más general al más concreto.
+    pub headings: Vec<String>,
+}
+
+impl MarkdownContext {
+    /// Ruta de encabezados unida con [`SECTION_SEPARATOR`].
+    pub fn section(&self) -> String {
+        self.headings.join(SECTION_SEPARATOR)
+    }
+
+    /// Indica si la celda está dentro de la sección `title`: un encabezado
+    /// cualquiera de la ruta, o una ruta parcial (`Preprocesado > Limpieza`).
+    pub fn in_section(&self, title: &str) -> bool {
+        let path: Vec<&str> = title.split('>').map(str::trim).collect();
+        self.headings
+            .windows(path.len())
+            .any(|window| window.iter().zip(&path).all(|(h, t)| h == t))
+    }
+}
+
+/// Contexto markdown de cada celda del notebook, por índice.
+pub fn contexts(notebook: &Notebook) -> Vec<MarkdownContext> {
+    let mut result = Vec::with_capacity(notebook.cells.len());
+    let mut stack: Vec<(usize, String)> = Vec::new();
+    let mut markdown: Option<String> = None;
+    for cell in &notebook.cells {
+        result.push(MarkdownContext {
+            markdown: markdown.clone(),
+            headings: stack.iter().map(|(_, title)| title.clone()).collect(),
+        });
+        if cell.cell_type == CellType::Markdown {
+            let text = cell.source.concat();
+            for (level, title) in headings(&text) {
+                while stack.last().is_some_and(|(l, _)| *l >= level) {
+                    stack.pop();
+                }
+                stack.push((level, title));
+            }
+            markdown = Some(text.trim().to_string());
+        }
+    }
+    result
+}
+
+/// Encabezados ATX (`## Título`) de un texto markdown, con su nivel. Las
+/// líneas de los bloques de código (```` ``` ````, `~~~`) no cuentan.
+pub fn headings(text: &str) -> Vec<(usize, String)> {
+    let mut result = Vec::new();
+    let mut in_code = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        if let Some(caps) = HEADING_REGEX.captures(line) {
+            let title = caps.get(2).map_or("", |m| m.as_str()).trim();
+            if !title.is_empty() {
+                result.push((caps[1].len(), title.to_string()));
+            }
+        }
+    }
+    result
+}
@@ -0,0 +1,584 @@
+//! Análisis sintáctico de las celdas con el módulo `ast` de Python.
+//!
+//! Los límites de imports, funciones, clases y asignaciones salen de los
+//! nodos de sentencia (`lineno`/`end_lineno`) y no de la indentación de las
+//! líneas: un docstring o un string SQL con líneas en la columna 0 no corta
+//! una función, y un `def x(` dentro de un string no crea una función falsa.
+
+use crate::ensure_python_initialized;
+use pyo3::exceptions::PyIndexError;
+use pyo3::prelude::*;
+use regex::Regex;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatementKind {
+    /// `import ...` o `from ... import ...`.
+    Import,
+    /// `try`/`if` de nivel raíz que protege imports (imports de respaldo o
+    /// según versión): sus ramas solo tienen imports, `pass` y alias de
+    /// respaldo (`redondear = int`). `loaded` son los nombres que leen las
+    /// condiciones de los `if`. Los `if __name__ == "__main__":` no cuentan.
+    GuardedImport {
+        loaded: Vec<String>,
+    },
+    Function {
+        name: String,
+        is_async: bool,
+    },
+    Class {
+        name: String,
+    },
+    /// `f = lambda ...` o `f = functools.partial(...)`.
+    CallableAssignment {
+        name: String,
+    },
+    /// Asignación a nombres simples cuyo valor solo tiene literales, nombres,
+    /// atributos, operadores, contenedores, las llamadas de `PURE_CALLS` e
+    /// índices sobre literales o los tipos de `GENERIC_TYPES`: los
+    /// nombres asignados y los que lee el valor (sin contar las variables de
+    /// comprensiones).
+    Assignment {
+        targets: Vec<String>,
+        loaded: Vec<String>,
+    },
+    Other,
+}
+
+/// Llamadas que puede tener el valor de una global: constructores sin efectos
+/// secundarios. Cualquier otra (`open(path)`, `pd.read_csv(...)`, `input()`)
+/// puede hacer E/S y se repetiría en cada ejecución del código generado.
+const PURE_CALLS: [&str; 16] = [
+    "Path",
+    "PurePath",
+    "pathlib.Path",
+    "pathlib.PurePath",
+    "frozenset",
+    "set",
+    "tuple",
+    "list",
+    "dict",
+    "range",
+    "re.compile",
+    "timedelta",
+    "datetime.timedelta",
+    "Decimal",
+    "decimal.Decimal",
+    "os.path.join",
+];
+
+/// Tipos genéricos que se pueden indexar en el valor de una global
+/// (`Vector = list[float]`). Indexar otra cosa (`os.environ["HOME"]`) puede
+/// lanzar `KeyError` fuera del entorno del notebook.
+const GENERIC_TYPES: [&str; 21] = [
+    "list",
+    "dict",
+    "tuple",
+    "set",
+    "frozenset",
+    "type",
+    "Optional",
+    "Union",
+    "List",
+    "Dict",
+    "Tuple",
+    "Set",
+    "FrozenSet",
+    "Type",
+    "Callable",
+    "Literal",
+    "Annotated",
+    "Sequence",
+    "Mapping",
+    "Iterable",
+    "Iterator",
+];
+
+/// Sentencia de nivel raíz de una celda.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub kind: StatementKind,
+    /// Primera línea (0-based), la del primer decorador si lo hay.
+    pub start: usize,
+    /// Última línea (0-based, inclusiva).
+    pub end: usize,
+    /// Expresiones de los decoradores, sin la `@`.
+    pub decorators: Vec<String>,
+    /// Nombres que lee la definición de una función o clase al ejecutarse:
+    /// decoradores, valores por defecto, anotaciones, bases y cuerpo de la
+    /// clase (los cuerpos de las funciones no, se leen al llamarlas).
+    pub definition_names: Vec<String>,
+}
+
+impl Statement {
+    /// Sentencias con cuerpo indentado.
+    fn is_block(&self) -> bool {
+        matches!(
+            self.kind,
+            StatementKind::Function { .. }
+                | StatementKind::Class { .. }
+                | StatementKind::GuardedImport { .. }
+        )
+    }
+
+    /// Texto de la sentencia tal y como está en la celda.
+    pub fn code(&self, code_lines: &[String]) -> String {
+        code_lines
+            .iter()
+            .take(self.end + 1)
+            .skip(self.start)
+            .map(|l| l.clone() + "\n")
+            .collect()
+    }
+}
+
+/// Sentencias de nivel raíz de una celda. Si la celda no compila, se analizan
+/// por separado los trozos que empiezan en la columna 0 y se descartan los
+/// que tienen errores, para no perder el resto de la celda.
+pub fn parse_cell(code_lines: &[String]) -> Vec<Statement> {
+    ensure_python_initialized();
+
+    Python::attach(|py| {
+        if let Ok(statements) = parse_source(py, code_lines, 0) {
+            return statements;
+        }
+        chunks(code_lines)
+            .into_iter()
+            .flat_map(|(start, end)| parse_chunk(py, &code_lines[start..end], start))
+            .collect()
+    })
+}
+
+/// Sentencias de un trozo; si tiene un error se conservan las anteriores a él.
+fn parse_chunk(py: Python<'_>, lines: &[String], offset: usize) -> Vec<Statement> {
+    let error = match parse_source(py, lines, offset) {
+        Ok(statements) => return statements,
+        Err(error) => error,
+    };
+    let error_line = match error
+        .value(py)
+        .getattr("lineno")
+        .and_then(|l| l.extract::<usize>())
+    {
+        Ok(line) if line > 1 && line <= lines.len() => line - 1,
+        _ => return Vec::new(),
+    };
+    let mut statements = parse_chunk(py, &lines[..error_line], offset);
+    // Un bloque justo antes de una línea indentada con error puede estar incompleto
+    if lines[error_line].starts_with([' ', '\t'])
+        && statements.last().is_some_and(Statement::is_block)
+    {
+        statements.pop();
+    }
+    statements
+}
+
+fn parse_source(py: Python<'_>, lines: &[String], offset: usize) -> PyResult<Vec<Statement>> {
+    let ast = py.import("ast")?;
+    let tree = ast.call_method1("parse", (lines.join("\n"),))?;
+    let mut statements = Vec::new();
+    for node in tree.getattr("body")?.try_iter()? {
+        let mut statement = statement(&ast, &node?, lines)?;
+        if statement.end >= lines.len() {
+            return Err(PyIndexError::new_err("Statement outside the cell"));
+        }
+        statement.start += offset;
+        statement.end += offset;
+        statements.push(statement);
+    }
+    Ok(statements)
+}
+
+fn statement(
+    ast: &Bound<'_, PyModule>,
+    node: &Bound<'_, PyAny>,
+    lines: &[String],
+) -> PyResult<Statement> {
+    let mut start = line(node, "lineno")?;
+    let mut decorators = Vec::new();
+    if node.hasattr("decorator_list")? {
+        for decorator in node.getattr("decorator_list")?.try_iter()? {
+            let decorator = decorator?;
+            start = start.min(line(&decorator, "lineno")?);
+            decorators.push(source_segment(&decorator, lines)?);
+        }
+    }
+    let kind = match node_type(node)?.as_str() {
+        "Import" | "ImportFrom" => StatementKind::Import,
+        "If" if reads_main_guard(ast, node)? => StatementKind::Other,
+        "Try" | "TryStar" | "If" => match guarded_import(ast, node)? {
+            Some(loaded) => StatementKind::GuardedImport { loaded },
+            None => StatementKind::Other,
+        },
+        kind @ ("FunctionDef" | "AsyncFunctionDef") => StatementKind::Function {
+            name: node.getattr("name")?.extract()?,
+            is_async: kind == "AsyncFunctionDef",
+        },
+        "ClassDef" => StatementKind::Class {
+            name: node.getattr("name")?.extract()?,
+        },
+        "Assign" | "AnnAssign" => assignment_kind(ast, node)?,
+        _ => StatementKind::Other,
+    };
+    let mut names = Vec::new();
+    if matches!(
+        kind,
+        StatementKind::Function { .. } | StatementKind::Class { .. }
+    ) {
+        definition_names(ast, node, &mut names)?;
+    }
+    Ok(Statement {
+        kind,
+        start,
+        end: line(node, "end_lineno")?,
+        decorators,
+        definition_names: names,
+    })
+}
+
+/// Añade a `names` los nombres que lee la sentencia al ejecutarse. De las
+/// funciones solo cuentan la cabecera y los decoradores; de las clases, todo
+/// salvo los cuerpos de sus métodos.
+fn definition_names(
+    ast: &Bound<'_, PyModule>,
+    node: &Bound<'_, PyAny>,
+    names: &mut Vec<String>,
+) -> PyResult<()> {
+    let mut parts = Vec::new();
+    match node_type(node)?.as_str() {
+        "FunctionDef" | "AsyncFunctionDef" => {
+            parts.extend(node.getattr("decorator_list")?.try_iter()?);
+            parts.push(Ok(node.getattr("args")?));
+            parts.push(Ok(node.getattr("returns")?));
+        }
+        "ClassDef" => {
+            parts.extend(node.getattr("decorator_list")?.try_iter()?);
+            parts.extend(node.getattr("bases")?.try_iter()?);
+            parts.extend(node.getattr("keywords")?.try_iter()?);
+            for child in node.getattr("body")?.try_iter()? {
+                let child = child?;
+                if matches!(
+                    node_type(&child)?.as_str(),
+                    "FunctionDef" | "AsyncFunctionDef" | "ClassDef"
+                ) {
+                    definition_names(ast, &child, names)?;
+                } else {
+                    parts.push(Ok(child));
+                }
+            }
+        }
+        _ => parts.push(Ok(node.clone())),
+    }
+    for part in parts {
+        let part = part?;
+        if part.is_none() {
+            continue;
+        }
+        for child in walk(ast, &part)? {
+            if node_type(&child)? == "Name" {
+                names.push(child.getattr("id")?.extract()?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Clasifica una asignación: callable (lambda/partial), asignación a nombres
+/// simples u otra cosa (atributos, subíndices, anotación sin valor).
+fn assignment_kind(ast: &Bound<'_, PyModule>, node: &Bound<'_, PyAny>) -> PyResult<StatementKind> {
+    let value = node.getattr("value")?;
+    if value.is_none() {
+        return Ok(StatementKind::Other);
+    }
+    let targets: Vec<Bound<'_, PyAny>> = if node.hasattr("targets")? {
+        node.getattr("targets")?.extract()?
+    } else {
+        vec![node.getattr("target")?]
+    };
+    if let [target] = targets.as_slice()
+        && node_type(target)? == "Name"
+        && is_callable_value(&value)?
+    {
+        return Ok(StatementKind::CallableAssignment {
+            name: target.getattr("id")?.extract()?,
+        });
+    }
+
+    let mut assigned = Vec::new();
+    for target in &targets {
+        for node in walk(ast, target)? {
+            match node_type(&node)?.as_str() {
+                "Name" => assigned.push(node.getattr("id")?.extract()?),
+                "Tuple" | "List" | "Starred" | "Store" => {}
+                _ => return Ok(StatementKind::Other),
+            }
+        }
+    }
+
+    let mut loaded = Vec::new();
+    let mut local = HashSet::new();
+    for node in walk(ast, &value)? {
+        match node_type(&node)?.as_str() {
+            "Name" => {
+                let id: String = node.getattr("id")?.extract()?;
+                if node_type(&node.getattr("ctx")?)? == "Load" {
+                    loaded.push(id);
+                } else {
+                    local.insert(id);
+                }
+            }
+            "Call" => {
+                let pure = dotted_name(&node.getattr("func")?)?
+                    .is_some_and(|name| PURE_CALLS.contains(&name.as_str()));
+                if !pure {
+                    return Ok(StatementKind::Other);
+                }
+            }
+            "Subscript" => {
+                let base = node.getattr("value")?;
+                let literal = matches!(
+                    node_type(&base)?.as_str(),
+                    "Constant" | "List" | "Tuple" | "Dict" | "Set"
+                );
+                let generic = dotted_name(&base)?.is_some_and(|name| {
+                    let name = name.strip_prefix("typing.").unwrap_or(&name);
+                    GENERIC_TYPES.contains(&name)
+                });
+                if !literal && !generic {
+                    return Ok(StatementKind::Other);
+                }
+            }
+            "Await" | "Yield" | "YieldFrom" | "NamedExpr" => return Ok(StatementKind::Other),
+            _ => {}
+        }
+    }
+    loaded.retain(|name| !local.contains(name));
+    Ok(StatementKind::Assignment {
+        targets: assigned,
+        loaded,
+    })
+}
+
+/// `lambda ...`, `partial(...)` o `functools.partial(...)`.
+fn is_callable_value(value: &Bound<'_, PyAny>) -> PyResult<bool> {
+    match node_type(value)?.as_str() {
+        "Lambda" => Ok(true),
+        "Call" => {
+            let func = value.getattr("func")?;
+            Ok(match node_type(&func)?.as_str() {
+                "Name" => func.getattr("id")?.extract::<String>()? == "partial",
+                "Attribute" => {
+                    let base = func.getattr("value")?;
+                    func.getattr("attr")?.extract::<String>()? == "partial"
+                        && node_type(&base)? == "Name"
+                        && base.getattr("id")?.extract::<String>()? == "functools"
+                }
+                _ => false,
+            })
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Nombre con puntos de una expresión `a.b.c`; `None` si no es solo nombres y atributos.
+fn dotted_name(node: &Bound<'_, PyAny>) -> PyResult<Option<String>> {
+    match node_type(node)?.as_str() {
+        "Name" => Ok(Some(node.getattr("id")?.extract()?)),
+        "Attribute" => {
+            let attr: String = node.getattr("attr")?.extract()?;
+            Ok(dotted_name(&node.getattr("value")?)?.map(|base| format!("{}.{}", base, attr)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Nombres que leen las condiciones de un `try`/`if` que solo protege imports,
+/// o `None` si alguna rama tiene otras sentencias o no hay ningún import.
+fn guarded_import(
+    ast: &Bound<'_, PyModule>,
+    node: &Bound<'_, PyAny>,
+) -> PyResult<Option<Vec<String>>> {
+    let mut loaded = Vec::new();
+    let mut imports = 0;
+    if !only_imports(ast, node, &mut loaded, &mut imports)? || imports == 0 {
+        return Ok(None);
+    }
+    Ok(Some(loaded))
+}
+
+/// Indica si `node` solo tiene imports, `pass`, alias de respaldo y bloques
+/// `try`/`if` con lo mismo. No entra en funciones ni clases.
+fn only_imports(
+    ast: &Bound<'_, PyModule>,
+    node: &Bound<'_, PyAny>,
+    loaded: &mut Vec<String>,
+    imports: &mut usize,
+) -> PyResult<bool> {
+    let mut blocks = Vec::new();
+    match node_type(node)?.as_str() {
+        "Import" | "ImportFrom" => {
+            *imports += 1;
+            return Ok(true);
+        }
+        "Pass" => return Ok(true),
+        "Assign" => return is_fallback_alias(node),
+        "If" => {
+            for child in walk(ast, &node.getattr("test")?)? {
+                if node_type(&child)? == "Name" {
+                    loaded.push(child.getattr("id")?.extract()?);
+                }
+            }
+            blocks.push(node.getattr("body")?);
+            blocks.push(node.getattr("orelse")?);
+        }
+        "Try" | "TryStar" => {
+            blocks.push(node.getattr("body")?);
+            for handler in node.getattr("handlers")?.try_iter()? {
+                blocks.push(handler?.getattr("body")?);
+            }
+            blocks.push(node.getattr("orelse")?);
+            blocks.push(node.getattr("finalbody")?);
+        }
+        _ => return Ok(false),
+    }
+    for block in blocks {
+        for child in block.try_iter()? {
+            if !only_imports(ast, &child?, loaded, imports)? {
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// `nombre = otro_nombre`, `nombre = modulo.atributo` o `nombre = None`: un
+/// alias que sustituye a un import que ha fallado.
+fn is_fallback_alias(node: &Bound<'_, PyAny>) -> PyResult<bool> {
+    let targets: Vec<Bound<'_, PyAny>> = node.getattr("targets")?.extract()?;
+    let value = node.getattr("value")?;
+    Ok(match targets.as_slice() {
+        [target] if node_type(target)? == "Name" => {
+            node_type(&value)? == "Constant" || dotted_name(&value)?.is_some()
+        }
+        _ => false,
+    })
+}
+
+/// Indica si la condición de un `if` lee `__name__`.
+fn reads_main_guard(ast: &Bound<'_, PyModule>, node: &Bound<'_, PyAny>) -> PyResult<bool> {
+    for child in walk(ast, &node.getattr("test")?)? {
+        if node_type(&child)? == "Name" && child.getattr("id")?.extract::<String>()? == "__name__" {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Texto de un nodo según sus posiciones (`col_offset` cuenta bytes UTF-8).
+fn source_segment(node: &Bound<'_, PyAny>, lines: &[String]) -> PyResult<String> {
+    let (first, last) = (line(node, "lineno")?, line(node, "end_lineno")?);
+    let start: usize = node.getattr("col_offset")?.extract()?;
+    let end: usize = node.getattr("end_col_offset")?.extract()?;
+    // Las posiciones vienen de Python: se comprueban en lugar de confiar en ellas
+    let out_of_bounds = || PyIndexError::new_err("Node position outside the cell");
+    if first == last {
+        return lines
+            .get(first)
+            .and_then(|l| l.get(start..end))
+            .map(String::from)
+            .ok_or_else(out_of_bounds);
+    }
+    let head = lines.get(first).and_then(|l| l.get(start..));
+    let tail = lines.get(last).and_then(|l| l.get(..end));
+    let (Some(head), Some(tail)) = (head, tail) else {
+        return Err(out_of_bounds());
+    };
+    let mut parts = vec![head];
+    parts.extend(lines[first + 1..last].iter().map(String::as_str));
+    parts.push(tail);
+    Ok(parts.join("\n"))
+}
+
+/// Atributo de línea de un nodo (`lineno`, `end_lineno`) pasado a 0-based.
+fn line(node: &Bound<'_, PyAny>, attribute: &str) -> PyResult<usize> {
+    Ok(node
+        .getattr(attribute)?
+        .extract::<usize>()?
+        .saturating_sub(1))
+}
+
+fn walk<'py>(
+    ast: &Bound<'py, PyModule>,
+    node: &Bound<'py, PyAny>,
+) -> PyResult<Vec<Bound<'py, PyAny>>> {
+    ast.call_method1("walk", (node,))?.try_iter()?.collect()
+}
+
+/// Nombre del tipo de un nodo del `ast` (`Assign`, `Name`...).
+fn node_type(node: &Bound<'_, PyAny>) -> PyResult<String> {
+    Ok(node.get_type().name()?.to_string())
+}
+
+/// Rangos `[inicio, fin)` de los trozos de nivel raíz de una celda que no
+/// compila: cada línea en la columna 0 empieza un trozo, salvo comentarios,
+/// cierres de paréntesis, cláusulas (`else`, `except`...) y la definición
+/// que sigue a sus decoradores.
+fn chunks(code_lines: &[String]) -> Vec<(usize, usize)> {
+    let continuation = Regex::new(r"^([)\]}#]|(else|elif|except|finally)\b)")
+        .expect("Error making the regex processing the code.");
+    let mut starts = Vec::new();
+    let mut decorating = false;
+    for (i, line) in code_lines.iter().enumerate() {
+        if line.is_empty() || line.starts_with([' ', '\t']) || continuation.is_match(line) {
+            continue;
+        }
+        if !decorating {
+            starts.push(i);
+        }
+        decorating = line.starts_with('@');
+    }
+    let mut ranges = Vec::new();
+    for (k, &start) in starts.iter().enumerate() {
+        let end = starts.get(k + 1).copied().unwrap_or(code_lines.len());
+        ranges.push((start, end));
+    }
+    ranges
+}
+
+/// Nombres que definen los imports (`import a.b` define `a`, `import x as y` define `y`).
+pub fn imported_names(imports: &HashSet<String>) -> HashSet<String> {
+    ensure_python_initialized();
+
+    Python::attach(|py| -> PyResult<HashSet<String>> {
+        let ast = py.import("ast")?;
+        let mut names = HashSet::new();
+        for import in imports {
+            let Ok(tree) = ast.call_method1("parse", (import,)) else {
+                continue;
+            };
+            for node in walk(&ast, &tree)? {
+                if node_type(&node)? != "alias" {
+                    continue;
+                }
+                let name: String = match node.getattr("asname")?.extract()? {
+                    Some(asname) => asname,
+                    None => node.getattr("name")?.extract()?,
+                };
+                names.insert(name.split('.').next().unwrap_or("").to_string());
+            }
+        }
+        Ok(names)
+    })
+    .unwrap_or_default()
+}
+
+pub fn builtin_names() -> HashSet<String> {
+    ensure_python_initialized();
+
+    Python::attach(|py| -> PyResult<Vec<String>> {
+        let builtins = py.import("builtins")?;
+        builtins.dir()?.extract()
+    })
+    .map(HashSet::from_iter)
+    .unwrap_or_default()
+}
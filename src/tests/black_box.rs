@@ -12,7 +12,10 @@
 //! No acceden a campos internos ni funciones privadas.
 
 use super::fixture_path;
-use crate::JupyterFunctions;
+use crate::{JupyterFunctions, LoadOptions, NotebookDecodeError, NotebookError};
+use pyo3::exceptions::{PyRuntimeError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
 
 // ============================================================================
 // Tests de creación de JupyterFunctions
@@ -304,3 +307,1629 @@ fn test_notebook_complejo_tiene_todas_las_funciones() {
         );
     }
 }
+
+// ============================================================================
+// Tests de formato del JSON del notebook
+// ============================================================================
+
+#[test]
+fn test_notebook_minificado_extrae_funciones() {
+    // Dado: un notebook en una sola línea y con las claves en otro orden
+    let path = fixture_path("minified_notebook.ipynb");
+
+    // Cuando: creamos una instancia
+    let jf = JupyterFunctions::new(path);
+
+    // Entonces: debe extraer funciones e imports igual que uno con formato
+    assert!(jf.exists_function(String::from("area_circulo")));
+    assert!(jf.exists_function(String::from("contar")));
+    assert!(jf.necessary_imports().join("").contains("import math"));
+}
+
+#[test]
+fn test_notebook_minificado_ignora_markdown() {
+    // Dado: un notebook minificado con una celda markdown que parece código
+    let path = fixture_path("minified_notebook.ipynb");
+
+    // Cuando: creamos una instancia
+    let jf = JupyterFunctions::new(path);
+
+    // Entonces: el contenido markdown no debe extraerse como función
+    assert!(!jf.exists_function(String::from("no_es_codigo")));
+}
+
+#[test]
+#[should_panic(expected = "Error parsing the notebook")]
+fn test_crear_jupyter_functions_json_invalido() {
+    // Dado: un archivo con JSON truncado
+    let path = fixture_path("invalid_notebook.ipynb");
+
+    // Cuando: intentamos crear una instancia
+    // Entonces: debe lanzar un panic indicando el error de parseo
+    let _jf = JupyterFunctions::new(path);
+}
+
+// ============================================================================
+// Tests de notebooks nbformat 3
+// ============================================================================
+
+#[test]
+fn test_notebook_v3_extrae_funciones_e_imports() {
+    // Dado: un notebook antiguo en nbformat 3 (worksheets / input)
+    let path = fixture_path("legacy_v3_notebook.ipynb");
+
+    // Cuando: creamos una instancia
+    let jf = JupyterFunctions::new(path);
+
+    // Entonces: debe extraer lo mismo que de un notebook v4
+    let nombres = jf.functions_names();
+    assert_eq!(nombres, vec!["hipotenusa", "mas_comun"]);
+    let imports = jf.necessary_imports().join("");
+    assert!(imports.contains("import math"));
+    assert!(imports.contains("from collections import Counter"));
+}
+
+#[test]
+fn test_nbformat_version_reporta_esquema() {
+    // Dado: un notebook v3 y otro v4
+    let v3 = JupyterFunctions::new(fixture_path("legacy_v3_notebook.ipynb"));
+    let v4 = JupyterFunctions::new(fixture_path("test_notebook.ipynb"));
+
+    // Cuando / Entonces: cada uno debe reportar la versión leída
+    assert_eq!(v3.nbformat_version(), Some((3, 0)));
+    assert_eq!(v4.nbformat_version(), Some((4, 4)));
+}
+
+#[test]
+fn test_nbformat_version_none_en_formatos_de_texto() {
+    // Dado: notebooks en formatos de texto, sin esquema nbformat
+    let percent = JupyterFunctions::new(fixture_path("percent_notebook.py"));
+    let myst = JupyterFunctions::new(fixture_path("myst_notebook.md"));
+
+    // Cuando / Entonces: no se inventa una versión
+    assert_eq!(percent.nbformat_version(), None);
+    assert_eq!(myst.nbformat_version(), None);
+}
+
+#[test]
+fn test_notebook_con_source_string_extrae_funciones() {
+    // Dado: un notebook cuyas celdas guardan el source como un único string
+    let path = fixture_path("string_source_notebook.ipynb");
+
+    // Cuando: creamos una instancia
+    let jf = JupyterFunctions::new(path);
+
+    // Entonces: debe extraer funciones e imports como con la forma de lista
+    assert_eq!(jf.functions_names(), vec!["doble", "leer_json"]);
+    let imports = jf.necessary_imports().join("");
+    assert!(imports.contains("import json"));
+    assert!(imports.contains("from pathlib import Path"));
+}
+
+// ============================================================================
+// Tests de scripts jupytext percent
+// ============================================================================
+
+#[test]
+fn test_script_percent_extrae_funciones_e_imports() {
+    // Dado: un script .py emparejado con jupytext en formato percent
+    let path = fixture_path("percent_notebook.py");
+
+    // Cuando: creamos una instancia
+    let jf = JupyterFunctions::new(path);
+
+    // Entonces: debe extraer funciones e imports como de un .ipynb
+    assert_eq!(
+        jf.functions_names(),
+        vec!["area_circulo", "mas_comun", "perimetro_circulo"]
+    );
+    let imports = jf.necessary_imports().join("");
+    assert!(imports.contains("import math"));
+    assert!(imports.contains("from collections import Counter"));
+}
+
+#[test]
+fn test_script_percent_ignora_celdas_markdown() {
+    // Dado: un script percent con una celda markdown que parece código
+    let path = fixture_path("percent_notebook.py");
+
+    // Cuando: creamos una instancia
+    let jf = JupyterFunctions::new(path);
+
+    // Entonces: el markdown no debe extraerse como función
+    assert!(!jf.exists_function(String::from("no_es_codigo")));
+}
+
+// ============================================================================
+// Tests de documentos Markdown (MyST, Quarto, R Markdown)
+// ============================================================================
+
+#[test]
+fn test_quarto_extrae_solo_bloques_python() {
+    // Dado: un documento Quarto con bloques python, bash y r
+    let path = fixture_path("quarto_report.qmd");
+
+    // Cuando: creamos una instancia
+    let jf = JupyterFunctions::new(path);
+
+    // Entonces: solo deben extraerse las funciones de los bloques python
+    assert_eq!(jf.functions_names(), vec!["media"]);
+    assert!(
+        jf.necessary_imports()
+            .join("")
+            .contains("import statistics")
+    );
+}
+
+#[test]
+fn test_rmarkdown_con_opciones_de_chunk() {
+    // Dado: un documento R Markdown con chunks {python, opciones}
+    let path = fixture_path("rmarkdown_report.Rmd");
+
+    // Cuando: creamos una instancia
+    let jf = JupyterFunctions::new(path);
+
+    // Entonces: los chunks python se procesan y los de R se ignoran
+    assert_eq!(jf.functions_names(), vec!["raiz"]);
+    assert!(jf.necessary_imports().join("").contains("import math"));
+}
+
+#[test]
+fn test_myst_code_cell_descarta_opciones() {
+    // Dado: un notebook MyST con directivas {code-cell} y opciones
+    let path = fixture_path("myst_notebook.md");
+
+    // Cuando: creamos una instancia
+    let jf = JupyterFunctions::new(path);
+
+    // Entonces: las opciones no rompen el código y se extrae todo
+    assert_eq!(jf.functions_names(), vec!["cuadruple", "frecuencias"]);
+    assert!(
+        jf.necessary_imports()
+            .join("")
+            .contains("from collections import Counter")
+    );
+}
+
+// ============================================================================
+// Tests de construcción desde contenido en memoria
+// ============================================================================
+
+fn leer_fixture(name: &str) -> String {
+    std::fs::read_to_string(fixture_path(name)).unwrap()
+}
+
+#[test]
+fn test_from_string_equivale_a_ruta() {
+    // Dado: el contenido de un notebook ya cargado en memoria
+    let contenido = leer_fixture("test_notebook.ipynb");
+
+    // Cuando: creamos la instancia desde el string y desde la ruta
+    let desde_string = JupyterFunctions::from_string(&contenido, "ipynb", None).unwrap();
+    let desde_ruta = JupyterFunctions::new(fixture_path("test_notebook.ipynb"));
+
+    // Entonces: ambos modelos deben ser iguales
+    assert_eq!(desde_string.functions, desde_ruta.functions);
+    assert_eq!(desde_string.imports, desde_ruta.imports);
+    assert_eq!(
+        desde_string.nbformat_version(),
+        desde_ruta.nbformat_version()
+    );
+}
+
+#[test]
+fn test_from_string_con_formato_percent() {
+    // Dado: el contenido de un script percent
+    let contenido = leer_fixture("percent_notebook.py");
+
+    // Cuando: lo cargamos indicando el formato
+    let jf = JupyterFunctions::from_string(&contenido, "percent", None).unwrap();
+
+    // Entonces: debe extraer sus funciones
+    assert!(jf.exists_function(String::from("area_circulo")));
+}
+
+#[test]
+fn test_from_string_formato_desconocido() {
+    // Dado: un formato que no existe
+    // Cuando: intentamos crear la instancia
+    let resultado = JupyterFunctions::from_string("{}", "docx", None);
+
+    // Entonces: debe devolver error
+    assert!(resultado.is_err());
+}
+
+#[test]
+fn test_from_string_json_invalido() {
+    // Dado: contenido que no es un notebook
+    // Cuando: intentamos crear la instancia
+    let resultado = JupyterFunctions::from_string("no es json", "ipynb", None);
+
+    // Entonces: debe devolver error en lugar de hacer panic
+    assert!(resultado.is_err());
+}
+
+#[test]
+fn test_from_bytes_equivale_a_ruta() {
+    // Dado: los bytes de un notebook
+    let contenido = leer_fixture("complex_notebook.ipynb");
+
+    // Cuando: creamos la instancia desde bytes
+    let jf = JupyterFunctions::from_bytes(contenido.as_bytes(), "ipynb", None).unwrap();
+
+    // Entonces: debe tener las mismas funciones que desde la ruta
+    let desde_ruta = JupyterFunctions::new(fixture_path("complex_notebook.ipynb"));
+    assert_eq!(jf.functions, desde_ruta.functions);
+}
+
+#[test]
+fn test_from_bytes_no_utf8() {
+    // Dado: bytes que no son UTF-8 válido
+    let bytes = [0x7b, 0xff, 0xfe, 0x7d];
+
+    // Cuando: intentamos crear la instancia
+    let resultado = JupyterFunctions::from_bytes(&bytes, "ipynb", None);
+
+    // Entonces: debe devolver error
+    assert!(resultado.is_err());
+}
+
+#[test]
+fn test_from_dict_equivale_a_ruta() {
+    // Dado: un notebook cargado como dict de Python
+    let contenido = leer_fixture("test_notebook.ipynb");
+    Python::attach(|py| {
+        let json = py.import("json").unwrap();
+        let dict = json.call_method1("loads", (contenido,)).unwrap();
+
+        // Cuando: creamos la instancia desde el dict
+        let jf = JupyterFunctions::from_dict(&dict, None).unwrap();
+
+        // Entonces: debe tener las mismas funciones que desde la ruta
+        let desde_ruta = JupyterFunctions::new(fixture_path("test_notebook.ipynb"));
+        assert_eq!(jf.functions, desde_ruta.functions);
+    });
+}
+
+#[test]
+fn test_from_file_binario_y_texto() {
+    // Dado: un notebook abierto como objeto archivo de texto y binario
+    let contenido = leer_fixture("test_notebook.ipynb");
+    Python::attach(|py| {
+        let io = py.import("io").unwrap();
+        let texto = io.call_method1("StringIO", (contenido.clone(),)).unwrap();
+        let binario = io
+            .call_method1("BytesIO", (PyBytes::new(py, contenido.as_bytes()),))
+            .unwrap();
+
+        // Cuando: creamos las instancias desde ambos archivos
+        let desde_texto = JupyterFunctions::from_file(&texto, None, None).unwrap();
+        let desde_binario = JupyterFunctions::from_file(&binario, None, None).unwrap();
+
+        // Entonces: ambos deben dar el mismo modelo
+        assert_eq!(desde_texto.functions, desde_binario.functions);
+        assert!(desde_texto.exists_function(String::from("suma")));
+    });
+}
+
+#[test]
+fn test_from_file_deduce_formato_del_nombre() {
+    // Dado: un script percent abierto con open()
+    let path = fixture_path("percent_notebook.py");
+    Python::attach(|py| {
+        let builtins = py.import("builtins").unwrap();
+        let archivo = builtins.call_method1("open", (path, "rb")).unwrap();
+
+        // Cuando: creamos la instancia sin indicar formato
+        let jf = JupyterFunctions::from_file(&archivo, None, None).unwrap();
+        archivo.call_method0("close").unwrap();
+
+        // Entonces: el formato se deduce de la extensión de `name`
+        assert!(jf.exists_function(String::from("mas_comun")));
+    });
+}
+
+// ============================================================================
+// Tests de notebooks comprimidos y dentro de archivos
+// ============================================================================
+
+#[test]
+fn test_notebook_gzip_equivale_al_original() {
+    // Dado: un notebook comprimido con gzip
+    let path = fixture_path("test_notebook.ipynb.gz");
+
+    // Cuando: creamos la instancia directamente desde el .gz
+    let jf = JupyterFunctions::new(path);
+
+    // Entonces: debe tener las mismas funciones que el original
+    let original = JupyterFunctions::new(fixture_path("test_notebook.ipynb"));
+    assert_eq!(jf.functions, original.functions);
+    assert_eq!(jf.imports, original.imports);
+}
+
+#[test]
+fn test_miembro_de_zip_con_separador() {
+    // Dado: un notebook dentro de un .zip indicado con `archivo!miembro`
+    let path = format!(
+        "{}!analysis/complex.ipynb",
+        fixture_path("notebooks_archive.zip")
+    );
+
+    // Cuando: creamos la instancia
+    let jf = JupyterFunctions::new(path);
+
+    // Entonces: debe extraer las funciones del miembro
+    assert!(jf.exists_function(String::from("funcion_con_closure")));
+}
+
+#[test]
+fn test_miembro_de_zip_comprimido_y_percent() {
+    // Dado: miembros .ipynb.gz y .py dentro de un zip
+    let zip = fixture_path("notebooks_archive.zip");
+
+    // Cuando: los cargamos con from_archive
+    let gz = JupyterFunctions::from_archive(&zip, "analysis/test.ipynb.gz", None, None).unwrap();
+    let py = JupyterFunctions::from_archive(&zip, "scripts/percent.py", None, None).unwrap();
+
+    // Entonces: se descomprime el .gz y el formato se deduce del nombre
+    assert!(gz.exists_function(String::from("factorial")));
+    assert!(py.exists_function(String::from("area_circulo")));
+}
+
+#[test]
+fn test_miembro_de_tar_gz() {
+    // Dado: un notebook dentro de un .tar.gz (guardado con prefijo ./)
+    let path = format!(
+        "{}!snapshot/complex.ipynb",
+        fixture_path("notebooks_archive.tar.gz")
+    );
+
+    // Cuando: creamos la instancia
+    let jf = JupyterFunctions::new(path);
+
+    // Entonces: debe extraer las funciones del miembro
+    assert!(jf.exists_function(String::from("funcion_despues_markdown")));
+}
+
+#[test]
+fn test_miembro_inexistente_en_archivo() {
+    // Dado: un miembro que no está en el zip
+    let zip = fixture_path("notebooks_archive.zip");
+
+    // Cuando: intentamos cargarlo
+    let resultado = JupyterFunctions::from_archive(&zip, "no/existe.ipynb", None, None);
+
+    // Entonces: debe devolver error
+    assert!(resultado.is_err());
+}
+
+// ============================================================================
+// Tests de notebooks Databricks
+// ============================================================================
+
+#[test]
+fn test_databricks_extrae_funciones_e_ignora_magic() {
+    // Dado: un notebook Databricks exportado como .py
+    let path = fixture_path("databricks_notebook.py");
+
+    // Cuando: creamos una instancia
+    let jf = JupyterFunctions::new(path);
+
+    // Entonces: debe extraer las funciones Python, también las de %python
+    assert_eq!(jf.functions_names(), vec!["anio_actual", "normalizar"]);
+    assert!(!jf.exists_function(String::from("no_es_codigo")));
+    let imports = jf.necessary_imports().join("");
+    assert!(imports.contains("import re"));
+    assert!(imports.contains("from datetime import date"));
+}
+
+// ============================================================================
+// Tests de notebooks marimo
+// ============================================================================
+
+#[test]
+fn test_marimo_extrae_funciones_de_celdas() {
+    // Dado: un notebook de marimo con funciones dentro de @app.cell
+    let path = fixture_path("marimo_notebook.py");
+
+    // Cuando: creamos una instancia
+    let jf = JupyterFunctions::new(path);
+
+    // Entonces: debe exponer las funciones de las celdas y las @app.function
+    assert_eq!(
+        jf.functions_names(),
+        vec!["area_circulo", "mas_comun", "perimetro_circulo"]
+    );
+    assert!(!jf.exists_function(String::from("_")));
+    assert!(!jf.exists_function(String::from("no_es_codigo")));
+}
+
+#[test]
+fn test_marimo_incluye_imports_del_setup() {
+    // Dado: un notebook de marimo con bloque `with app.setup:`
+    let path = fixture_path("marimo_notebook.py");
+
+    // Cuando: creamos una instancia
+    let jf = JupyterFunctions::new(path);
+
+    // Entonces: los imports del setup y de las celdas deben estar disponibles
+    let imports = jf.necessary_imports().join("");
+    assert!(imports.contains("import math"));
+    assert!(imports.contains("from collections import Counter"));
+    assert!(imports.contains("import marimo as mo"));
+}
+
+// ============================================================================
+// Tests de codificación: BOM, CRLF y bytes no UTF-8
+// ============================================================================
+
+#[test]
+fn test_notebook_windows_con_bom_y_crlf() {
+    // Dado: el mismo notebook guardado en Windows (BOM + CRLF)
+    let jf = JupyterFunctions::new(fixture_path("windows_notebook.ipynb"));
+
+    // Cuando: lo comparamos con el original
+    let original = JupyterFunctions::new(fixture_path("test_notebook.ipynb"));
+
+    // Entonces: no deben quedar `\r` y el modelo debe ser el mismo
+    assert_eq!(jf.functions, original.functions);
+    assert_eq!(jf.imports, original.imports);
+    assert!(jf.functions.iter().all(|f| !f.code.contains('\r')));
+}
+
+#[test]
+fn test_script_percent_windows_con_bom_y_crlf() {
+    // Dado: un script percent con BOM y CRLF
+    let jf = JupyterFunctions::new(fixture_path("windows_percent.py"));
+
+    // Cuando: lo comparamos con el original
+    let original = JupyterFunctions::new(fixture_path("percent_notebook.py"));
+
+    // Entonces: debe extraer exactamente lo mismo
+    assert_eq!(jf.functions, original.functions);
+    assert_eq!(jf.imports, original.imports);
+}
+
+#[test]
+fn test_notebook_no_utf8_error_con_offset() {
+    // Dado: un notebook guardado en Latin-1
+    let path = fixture_path("latin1_notebook.ipynb");
+
+    // Cuando: intentamos abrirlo
+    let error = JupyterFunctions::open(&path, &LoadOptions::default())
+        .err()
+        .unwrap();
+
+    // Entonces: el error es de decodificación e indica el byte exacto
+    assert!(matches!(error, NotebookError::Decode { offset: 528 }));
+    assert!(error.to_string().contains("offset 528"));
+}
+
+#[test]
+fn test_from_bytes_no_utf8_lanza_notebook_decode_error() {
+    // Dado: bytes con BOM seguidos de un byte inválido
+    let bytes = [0xEF, 0xBB, 0xBF, b'{', 0xFF, b'}'];
+
+    // Cuando: intentamos crear la instancia desde Python
+    let error = JupyterFunctions::from_bytes(&bytes, "ipynb", None)
+        .err()
+        .unwrap();
+
+    // Entonces: la excepción es NotebookDecodeError y el offset cuenta el BOM
+    Python::attach(|py| {
+        assert!(error.is_instance_of::<NotebookDecodeError>(py));
+        assert!(error.to_string().contains("offset 4"));
+    });
+}
+
+#[test]
+fn test_archivo_inexistente_lanza_file_not_found() {
+    // Dado: una ruta que no existe
+    let error = JupyterFunctions::open("/ruta/inexistente/notebook.ipynb", &LoadOptions::default())
+        .err()
+        .unwrap();
+
+    // Cuando: la convertimos en excepción de Python
+    let error: PyErr = error.into();
+
+    // Entonces: debe ser FileNotFoundError
+    Python::attach(|py| {
+        assert!(error.is_instance_of::<pyo3::exceptions::PyFileNotFoundError>(py));
+    });
+}
+
+// ============================================================================
+// Tests de procedencia de las funciones
+// ============================================================================
+
+#[test]
+fn test_function_location_con_id_de_celda() {
+    // Dado: un notebook nbformat 4.5 con ids de celda y dos funciones en una celda
+    let jf = JupyterFunctions::new(fixture_path("string_source_notebook.ipynb"));
+
+    // Cuando: pedimos la ubicación de cada función
+    let leer = jf.function_location(String::from("leer_json")).unwrap();
+    let doble = jf.function_location(String::from("doble")).unwrap();
+
+    // Entonces: debe indicar la celda, su id y las líneas dentro de la celda
+    assert_eq!(leer.cell_index, 2);
+    assert_eq!(leer.cell_id.as_deref(), Some("0a1b2c"));
+    assert_eq!((leer.start_line, leer.end_line), (1, 2));
+    assert_eq!(doble.cell_index, 2);
+    assert_eq!((doble.start_line, doble.end_line), (4, 5));
+}
+
+#[test]
+fn test_function_location_sin_id_de_celda() {
+    // Dado: un notebook nbformat 4.4 (sin ids de celda)
+    let jf = JupyterFunctions::new(fixture_path("test_notebook.ipynb"));
+
+    // Cuando: pedimos la ubicación de una función
+    let factorial = jf.function_location(String::from("factorial")).unwrap();
+
+    // Entonces: el índice cuenta todas las celdas y no hay id
+    assert_eq!(factorial.cell_index, 3);
+    assert_eq!(factorial.cell_id, None);
+    assert_eq!((factorial.start_line, factorial.end_line), (1, 5));
+}
+
+#[test]
+fn test_function_location_funcion_inexistente() {
+    // Dado: un notebook con funciones
+    let jf = JupyterFunctions::new(fixture_path("test_notebook.ipynb"));
+
+    // Cuando / Entonces: una función que no existe no tiene ubicación
+    assert!(jf.function_location(String::from("no_existe")).is_none());
+}
+
+// ============================================================================
+// Tests de imports multilínea
+// ============================================================================
+
+#[test]
+fn test_imports_multilinea_completos() {
+    // Dado: un notebook con imports formateados por black/ruff
+    let jf = JupyterFunctions::new(fixture_path("formatted_imports_notebook.ipynb"));
+
+    // Cuando: obtenemos los imports
+    let imports = jf.necessary_imports();
+
+    // Entonces: cada import debe estar completo
+    assert_eq!(imports.len(), 2);
+    let imports = imports.join("");
+    assert!(
+        imports.contains("from os.path import (\n    basename,\n    join,  # ruta completa\n)")
+    );
+    assert!(imports.contains("from collections import \\\n    OrderedDict"));
+}
+
+#[test]
+fn test_exec_function_con_imports_multilinea() {
+    // Dado: funciones que dependen de imports multilínea
+    let jf = JupyterFunctions::new(fixture_path("formatted_imports_notebook.ipynb"));
+
+    Python::attach(|py| {
+        // Cuando: ejecutamos la función
+        let args = PyTuple::new(py, ["datos", "tabla.csv"]).unwrap();
+        let resultado = jf.exec_function(py, "ruta_base", &args, None).unwrap();
+
+        // Entonces: el código generado es válido y devuelve el resultado
+        assert_eq!(resultado.extract::<String>(py).unwrap(), "tabla.csv");
+    });
+}
+
+// ============================================================================
+// Tests de imports protegidos (try/except, if)
+// ============================================================================
+
+#[test]
+fn test_imports_protegidos_se_conservan_enteros() {
+    // Dado: un notebook con imports dentro de try/except y de if/else
+    let jf = JupyterFunctions::new(fixture_path("guarded_imports_notebook.ipynb"));
+
+    // Cuando: obtenemos los imports
+    let imports = jf.necessary_imports();
+
+    // Entonces: cada bloque debe conservar su estructura completa
+    assert!(imports.contains(&String::from(
+        "try:\n    import modulo_que_no_existe as json\nexcept ImportError:\n    import json\n"
+    )));
+    assert!(imports.contains(&String::from(
+        "if sys.version_info >= (3, 0):\n    from math import floor as redondear\nelse:\n    redondear = int\n"
+    )));
+    assert!(imports.contains(&String::from("import sys\n")));
+}
+
+#[test]
+fn test_imports_protegidos_ignoran_bloque_main() {
+    // Dado: un notebook con imports bajo `if __name__ == "__main__":`
+    let jf = JupyterFunctions::new(fixture_path("guarded_imports_notebook.ipynb"));
+
+    // Cuando / Entonces: ese bloque no se incluye en el código generado
+    assert!(!jf.necessary_imports().join("").contains("antigravity"));
+}
+
+#[test]
+fn test_exec_function_con_imports_protegidos() {
+    // Dado: funciones que usan nombres definidos en imports protegidos
+    let jf = JupyterFunctions::new(fixture_path("guarded_imports_notebook.ipynb"));
+
+    Python::attach(|py| {
+        // Cuando: ejecutamos las funciones
+        let args = PyTuple::new(py, [2.7]).unwrap();
+        let entero = jf.exec_function(py, "entero", &args, None).unwrap();
+        let dict = PyDict::new(py);
+        dict.set_item("b", 1).unwrap();
+        dict.set_item("a", 2).unwrap();
+        let args = PyTuple::new(py, [dict]).unwrap();
+        let json = jf.exec_function(py, "serializar", &args, None).unwrap();
+
+        // Entonces: se usa el import alternativo y el del if
+        assert_eq!(entero.extract::<i64>(py).unwrap(), 2);
+        assert_eq!(json.extract::<String>(py).unwrap(), "{\"a\": 2, \"b\": 1}");
+    });
+}
+
+#[test]
+fn test_import_protegido_lee_constante_del_notebook() {
+    // Dado: un `if` con imports cuya condición es una constante del notebook
+    let raw = r#"{"nbformat": 4, "nbformat_minor": 5, "cells": [
+        {"cell_type": "code", "metadata": {}, "source": "USE_FAST = False"},
+        {"cell_type": "code", "metadata": {}, "source": "if USE_FAST:\n    import ujson as json\nelse:\n    import json"},
+        {"cell_type": "code", "metadata": {}, "source": "def serializar(x):\n    return json.dumps(x)\n\n\ndef doble(x):\n    return 2 * x"}
+    ]}"#;
+    let jf = JupyterFunctions::from_string(raw, "ipynb", None).unwrap();
+
+    Python::attach(|py| {
+        // Cuando: ejecutamos las funciones
+        let doble = jf
+            .exec_function(py, "doble", &PyTuple::new(py, [4]).unwrap(), None)
+            .unwrap();
+        let json = jf
+            .exec_function(py, "serializar", &PyTuple::new(py, [[1, 2]]).unwrap(), None)
+            .unwrap();
+
+        // Entonces: la constante se define antes que el bloque que la lee
+        assert_eq!(doble.extract::<i64>(py).unwrap(), 8);
+        assert_eq!(json.extract::<String>(py).unwrap(), "[1, 2]");
+        let codigo = jf.generated_code();
+        assert!(codigo.find("USE_FAST = False").unwrap() < codigo.find("if USE_FAST:").unwrap());
+    });
+}
+
+#[test]
+fn test_bloques_que_no_solo_importan_no_son_imports_protegidos() {
+    // Dado: un `if` con una condición que no se puede evaluar, un `try` con
+    // efectos secundarios y un `if` que define una función
+    let raw = r#"{"nbformat": 4, "nbformat_minor": 5, "cells": [
+        {"cell_type": "code", "metadata": {}, "source": "import os\nUSE_FAST = os.environ.get('X') == '1'"},
+        {"cell_type": "code", "metadata": {}, "source": "if USE_FAST:\n    import ujson as json\nelse:\n    import json"},
+        {"cell_type": "code", "metadata": {}, "source": "try:\n    import math\n    print('SIDE EFFECT')\nexcept ImportError:\n    pass"},
+        {"cell_type": "code", "metadata": {}, "source": "DEBUG = True\nif DEBUG:\n    def dbg():\n        import pdb"},
+        {"cell_type": "code", "metadata": {}, "source": "def f():\n    return 1"}
+    ]}"#;
+    let jf = JupyterFunctions::from_string(raw, "ipynb", None).unwrap();
+
+    Python::attach(|py| {
+        // Cuando: ejecutamos una función que no depende de ellos
+        let resultado = jf
+            .exec_function(py, "f", &PyTuple::empty(py), None)
+            .unwrap();
+
+        // Entonces: funciona y ninguno de los bloques entra en el código generado
+        assert_eq!(resultado.extract::<i64>(py).unwrap(), 1);
+        assert_eq!(jf.necessary_imports(), vec!["import os\n"]);
+        let codigo = jf.generated_code();
+        assert!(!codigo.contains("SIDE EFFECT"));
+        assert!(!codigo.contains("pdb"));
+    });
+}
+
+// ============================================================================
+// Tests de decoradores
+// ============================================================================
+
+#[test]
+fn test_decorators_devuelve_expresiones() {
+    // Dado: un notebook con funciones decoradas
+    let jf = JupyterFunctions::new(fixture_path("decorators_notebook.ipynb"));
+
+    // Cuando: pedimos los decoradores
+    let fib = jf.decorators(String::from("fib")).unwrap();
+    let saludar = jf.decorators(String::from("saludar")).unwrap();
+
+    // Entonces: deben estar completos, incluso los que ocupan varias líneas
+    assert_eq!(fib, vec!["functools.lru_cache(maxsize=None)"]);
+    assert_eq!(saludar, vec!["registrar(\n    \"saludo\",\n)"]);
+    assert_eq!(jf.decorators(String::from("registrar")), Some(vec![]));
+    assert_eq!(jf.decorators(String::from("no_existe")), None);
+}
+
+#[test]
+fn test_codigo_de_funcion_incluye_decorador() {
+    // Dado: una función con decorador
+    let jf = JupyterFunctions::new(fixture_path("decorators_notebook.ipynb"));
+
+    // Cuando: obtenemos su código
+    let codigo = jf.get_function_code(String::from("fib")).unwrap();
+
+    // Entonces: el decorador va pegado a la definición
+    assert!(codigo.starts_with("@functools.lru_cache(maxsize=None)\ndef fib(n):"));
+}
+
+#[test]
+fn test_exec_function_aplica_decoradores() {
+    // Dado: una función con lru_cache y otra con un decorador del propio notebook
+    let jf = JupyterFunctions::new(fixture_path("decorators_notebook.ipynb"));
+
+    Python::attach(|py| {
+        // Cuando: obtenemos las funciones
+        let fib = jf.return_function(py, "fib").unwrap();
+        let saludar = jf.return_function(py, "saludar").unwrap();
+
+        // Entonces: se comportan como en el notebook
+        let fib = fib.bind(py);
+        assert!(fib.hasattr("cache_info").unwrap());
+        assert_eq!(fib.call1((30,)).unwrap().extract::<i64>().unwrap(), 832040);
+        let etiqueta = saludar.bind(py).getattr("etiqueta").unwrap();
+        assert_eq!(etiqueta.extract::<String>().unwrap(), "saludo");
+    });
+}
+
+// ============================================================================
+// Tests de funciones async
+// ============================================================================
+
+#[test]
+fn test_async_def_se_extrae_y_se_marca() {
+    // Dado: un notebook con corrutinas
+    let jf = JupyterFunctions::new(fixture_path("async_notebook.ipynb"));
+
+    // Cuando: consultamos sus funciones
+    let nombres = jf.functions_names();
+
+    // Entonces: aparecen las async y quedan marcadas como tales
+    assert_eq!(nombres, vec!["doble", "esperar", "reunir"]);
+    assert_eq!(jf.is_async(String::from("esperar")), Some(true));
+    assert_eq!(jf.is_async(String::from("reunir")), Some(true));
+    assert_eq!(jf.is_async(String::from("doble")), Some(false));
+    assert_eq!(jf.is_async(String::from("no_existe")), None);
+}
+
+#[test]
+fn test_exec_function_async_se_ejecuta_hasta_el_final() {
+    // Dado: una corrutina del notebook
+    let jf = JupyterFunctions::new(fixture_path("async_notebook.ipynb"));
+
+    Python::attach(|py| {
+        // Cuando: la ejecutamos con la configuración por defecto
+        let args = PyTuple::new(py, [1, 2, 3]).unwrap();
+        let resultado = jf.exec_function(py, "reunir", &args, None).unwrap();
+
+        // Entonces: devuelve el resultado ya esperado
+        let valores: Vec<i64> = resultado.extract(py).unwrap();
+        assert_eq!(valores, vec![2, 4, 6]);
+    });
+}
+
+#[test]
+fn test_exec_function_async_devuelve_awaitable() {
+    // Dado: una instancia cargada con la opción de devolver awaitables
+    let contenido = leer_fixture("async_notebook.ipynb");
+
+    Python::attach(|py| {
+        let options = PyDict::new(py);
+        options.set_item("return_awaitables", true).unwrap();
+        let jf = JupyterFunctions::from_string(&contenido, "ipynb", Some(&options)).unwrap();
+
+        // Cuando: ejecutamos la corrutina
+        let args = PyTuple::new(py, [21]).unwrap();
+        let corrutina = jf.exec_function(py, "esperar", &args, None).unwrap();
+
+        // Entonces: recibimos una corrutina que podemos esperar nosotros
+        let inspect = PyModule::import(py, "inspect").unwrap();
+        let es_corrutina = inspect.call_method1("iscoroutine", (&corrutina,)).unwrap();
+        assert!(es_corrutina.extract::<bool>().unwrap());
+        let asyncio = PyModule::import(py, "asyncio").unwrap();
+        let valor = asyncio.call_method1("run", (corrutina,)).unwrap();
+        assert_eq!(valor.extract::<i64>().unwrap(), 42);
+    });
+}
+
+#[test]
+fn test_exec_function_async_dentro_de_un_bucle_en_marcha() {
+    // Dado: una corrutina del notebook y un bucle de eventos ya en marcha
+    let jf = JupyterFunctions::new(fixture_path("async_notebook.ipynb"));
+
+    Python::attach(|py| {
+        let asyncio = PyModule::import(py, "asyncio").unwrap();
+        let bucle = asyncio.call_method0("new_event_loop").unwrap();
+        let eventos = PyModule::import(py, "asyncio.events").unwrap();
+        eventos
+            .call_method1("_set_running_loop", (&bucle,))
+            .unwrap();
+
+        // Cuando: la ejecutamos con la configuración por defecto
+        let args = PyTuple::new(py, [21]).unwrap();
+        let error = jf.exec_function(py, "esperar", &args, None).unwrap_err();
+        eventos
+            .call_method1("_set_running_loop", (py.None(),))
+            .unwrap();
+        bucle.call_method0("close").unwrap();
+
+        // Entonces: el error explica cómo obtener el awaitable
+        assert!(error.is_instance_of::<PyRuntimeError>(py));
+        assert!(error.to_string().contains("return_awaitables=True"));
+    });
+}
+
+// ============================================================================
+// Tests de clases
+// ============================================================================
+
+#[test]
+fn test_classes_names_lista_clases_de_nivel_raiz() {
+    // Dado: un notebook con dataclasses y clases normales
+    let jf = JupyterFunctions::new(fixture_path("classes_notebook.ipynb"));
+
+    // Cuando: pedimos los nombres de las clases
+    let nombres = jf.classes_names();
+
+    // Entonces: aparecen todas, y los métodos no cuentan como funciones
+    assert_eq!(nombres, vec!["Contador", "Cuadrado", "Rectangulo"]);
+    assert_eq!(jf.functions_names(), vec!["area", "nuevo_contador"]);
+}
+
+#[test]
+fn test_instantiate_crea_instancias() {
+    // Dado: una dataclass y una subclase suya
+    let jf = JupyterFunctions::new(fixture_path("classes_notebook.ipynb"));
+
+    Python::attach(|py| {
+        // Cuando: instanciamos con argumentos posicionales y con nombre
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("alto", 3.0).unwrap();
+        let args = PyTuple::new(py, [2.0]).unwrap();
+        let rectangulo = jf
+            .instantiate(py, "Rectangulo", &args, Some(&kwargs))
+            .unwrap();
+        let args = PyTuple::new(py, [4.0]).unwrap();
+        let cuadrado = jf.instantiate(py, "Cuadrado", &args, None).unwrap();
+
+        // Entonces: las instancias tienen sus métodos
+        let perimetro = rectangulo.call_method0(py, "perimetro").unwrap();
+        assert_eq!(perimetro.extract::<f64>(py).unwrap(), 10.0);
+        let perimetro = cuadrado.call_method0(py, "perimetro").unwrap();
+        assert_eq!(perimetro.extract::<f64>(py).unwrap(), 16.0);
+    });
+}
+
+#[test]
+fn test_clase_que_usa_funciones_del_notebook_al_definirse() {
+    // Dado: una clase con un método decorado por una función del notebook y un
+    // `default_factory` definido en una celda posterior
+    let raw = r#"{"nbformat": 4, "nbformat_minor": 5, "cells": [
+        {"cell_type": "code", "metadata": {}, "source": "from dataclasses import dataclass, field"},
+        {"cell_type": "code", "metadata": {}, "source": "def deco(fn):\n    fn.marcado = True\n    return fn"},
+        {"cell_type": "code", "metadata": {}, "source": "@dataclass\nclass Caja:\n    items: list = field(default_factory=vacia)\n\n    @deco\n    def total(self):\n        return len(self.items)"},
+        {"cell_type": "code", "metadata": {}, "source": "def vacia():\n    return []\n\n\ndef uno():\n    return 1"}
+    ]}"#;
+    let jf = JupyterFunctions::from_string(raw, "ipynb", None).unwrap();
+
+    Python::attach(|py| {
+        // Cuando: ejecutamos una función cualquiera e instanciamos la clase
+        let uno = jf
+            .exec_function(py, "uno", &PyTuple::empty(py), None)
+            .unwrap();
+        let caja = jf
+            .instantiate(py, "Caja", &PyTuple::empty(py), None)
+            .unwrap();
+
+        // Entonces: las funciones que lee la clase se definen antes que ella
+        assert_eq!(uno.extract::<i64>(py).unwrap(), 1);
+        let total = caja.call_method0(py, "total").unwrap();
+        assert_eq!(total.extract::<i64>(py).unwrap(), 0);
+        let marcado = caja
+            .getattr(py, "total")
+            .unwrap()
+            .getattr(py, "marcado")
+            .unwrap();
+        assert!(marcado.extract::<bool>(py).unwrap());
+    });
+}
+
+#[test]
+fn test_return_class_devuelve_la_clase() {
+    // Dado: una clase del notebook
+    let jf = JupyterFunctions::new(fixture_path("classes_notebook.ipynb"));
+
+    Python::attach(|py| {
+        // Cuando: la pedimos sin instanciar
+        let clase = jf.return_class(py, "Contador").unwrap();
+
+        // Entonces: es una clase que podemos usar
+        let clase = clase.bind(py);
+        assert_eq!(
+            clase
+                .getattr("__name__")
+                .unwrap()
+                .extract::<String>()
+                .unwrap(),
+            "Contador"
+        );
+        let contador = clase.call1((5,)).unwrap();
+        let valor = contador.call_method1("incrementar", (2,)).unwrap();
+        assert_eq!(valor.extract::<i64>().unwrap(), 7);
+        assert!(jf.return_class(py, "area").is_err());
+    });
+}
+
+#[test]
+fn test_exec_function_usa_clases_del_notebook() {
+    // Dado: funciones que usan clases (en anotaciones y en el cuerpo)
+    let jf = JupyterFunctions::new(fixture_path("classes_notebook.ipynb"));
+
+    Python::attach(|py| {
+        // Cuando: ejecutamos las funciones
+        let args = PyTuple::new(py, [10]).unwrap();
+        let contador = jf.exec_function(py, "nuevo_contador", &args, None).unwrap();
+        let cuadrado = jf
+            .instantiate(py, "Cuadrado", &PyTuple::new(py, [3.0]).unwrap(), None)
+            .unwrap();
+        let area = jf
+            .exec_function(py, "area", &PyTuple::new(py, [cuadrado]).unwrap(), None)
+            .unwrap();
+
+        // Entonces: las clases están definidas
+        let valor = contador.getattr(py, "valor").unwrap();
+        assert_eq!(valor.extract::<i64>(py).unwrap(), 10);
+        assert_eq!(area.extract::<f64>(py).unwrap(), 9.0);
+    });
+}
+
+// ============================================================================
+// Tests de globales
+// ============================================================================
+
+#[test]
+fn test_globals_names_lista_constantes() {
+    // Dado: un notebook con constantes de nivel raíz
+    let jf = JupyterFunctions::new(fixture_path("globals_notebook.ipynb"));
+
+    // Cuando: pedimos los nombres de las globales
+    let nombres = jf.globals_names();
+
+    // Entonces: aparecen las constantes, pero no los resultados de ejecutar el notebook
+    assert_eq!(
+        nombres,
+        vec![
+            "ALTO",
+            "ANCHO",
+            "CONFIG",
+            "CUADRADOS",
+            "LABELS",
+            "THRESHOLD",
+            "limite"
+        ]
+    );
+}
+
+#[test]
+fn test_exec_function_lee_globales() {
+    // Dado: funciones que leen constantes del notebook
+    let jf = JupyterFunctions::new(fixture_path("globals_notebook.ipynb"));
+
+    Python::attach(|py| {
+        // Cuando: las ejecutamos
+        let args = PyTuple::new(py, [0.9]).unwrap();
+        let etiqueta = jf.exec_function(py, "clasificar", &args, None).unwrap();
+        let area = jf
+            .exec_function(py, "area", &PyTuple::empty(py), None)
+            .unwrap();
+
+        // Entonces: no hay NameError y vale la última redefinición de THRESHOLD
+        assert_eq!(etiqueta.extract::<String>(py).unwrap(), "bajo");
+        assert_eq!(area.extract::<i64>(py).unwrap(), 12);
+    });
+}
+
+#[test]
+fn test_global_que_fallaria_no_rompe_el_resto() {
+    // Dado: una global que lee una variable de entorno inexistente
+    let raw = r#"{"nbformat": 4, "nbformat_minor": 5, "cells": [
+        {"cell_type": "code", "metadata": {}, "source": "import os\nHOME_X = os.environ['NOPE_VAR_XYZ']\nLIMITE = 3"},
+        {"cell_type": "code", "metadata": {}, "source": "def limite():\n    return LIMITE"}
+    ]}"#;
+    let jf = JupyterFunctions::from_string(raw, "ipynb", None).unwrap();
+
+    Python::attach(|py| {
+        // Cuando: ejecutamos una función que no la lee
+        let resultado = jf
+            .exec_function(py, "limite", &PyTuple::empty(py), None)
+            .unwrap();
+
+        // Entonces: la global no se copia y la función no falla con KeyError
+        assert_eq!(resultado.extract::<i64>(py).unwrap(), 3);
+        assert_eq!(jf.globals_names(), vec!["LIMITE"]);
+    });
+}
+
+// ============================================================================
+// Tests de lambdas y partials
+// ============================================================================
+
+#[test]
+fn test_lambdas_y_partials_son_funciones() {
+    // Dado: un notebook con lambdas y partials asignados a nombres
+    let jf = JupyterFunctions::new(fixture_path("callables_notebook.ipynb"));
+
+    // Cuando: consultamos sus funciones
+    let nombres = jf.functions_names();
+
+    // Entonces: aparecen junto a los def, y no como globales
+    assert_eq!(
+        nombres,
+        vec!["cuadrado", "escalar", "normalizar", "sumar", "sumar_tres"]
+    );
+    assert!(jf.exists_function(String::from("sumar_tres")));
+    assert_eq!(jf.globals_names(), vec!["ESCALA"]);
+}
+
+#[test]
+fn test_exec_function_con_lambdas_y_partials() {
+    // Dado: un partial de una función definida en una celda posterior
+    let jf = JupyterFunctions::new(fixture_path("callables_notebook.ipynb"));
+
+    Python::attach(|py| {
+        // Cuando: ejecutamos lambdas y partials
+        let suma = jf
+            .exec_function(py, "sumar_tres", &PyTuple::new(py, [1, 2]).unwrap(), None)
+            .unwrap();
+        let cuadrado = jf
+            .exec_function(py, "cuadrado", &PyTuple::new(py, [7]).unwrap(), None)
+            .unwrap();
+        let escalado = jf
+            .exec_function(py, "escalar", &PyTuple::new(py, [5]).unwrap(), None)
+            .unwrap();
+        let normalizar = jf.return_function(py, "normalizar").unwrap();
+        let normalizados = normalizar.bind(py).call1((vec![1.0, 4.0],)).unwrap();
+
+        // Entonces: se comportan como en el notebook
+        assert_eq!(suma.extract::<i64>(py).unwrap(), 6);
+        assert_eq!(cuadrado.extract::<i64>(py).unwrap(), 49);
+        assert_eq!(escalado.extract::<i64>(py).unwrap(), 10);
+        assert_eq!(normalizados.extract::<Vec<f64>>().unwrap(), vec![0.25, 1.0]);
+    });
+}
+
+// ============================================================================
+// Tests de extracción con el ast de Python
+// ============================================================================
+
+#[test]
+fn test_strings_en_columna_cero_no_cortan_funciones() {
+    // Dado: una función con docstring y SQL en la columna 0, y un string con `def`
+    let jf = JupyterFunctions::new(fixture_path("syntax_notebook.ipynb"));
+
+    // Cuando: consultamos las funciones
+    let nombres = jf.functions_names();
+
+    // Entonces: el `def` del string no es una función y la celda rota no se pierde entera
+    assert_eq!(nombres, vec!["buena", "consulta", "otra", "siguiente"]);
+    assert_eq!(jf.globals_names(), vec!["PLANTILLA"]);
+}
+
+#[test]
+fn test_exec_function_con_sql_multilinea() {
+    // Dado: una función cuyo cuerpo tiene un string SQL en la columna 0
+    let jf = JupyterFunctions::new(fixture_path("syntax_notebook.ipynb"));
+
+    Python::attach(|py| {
+        // Cuando: la ejecutamos
+        let args = PyTuple::new(py, ["ventas"]).unwrap();
+        let sql = jf.exec_function(py, "consulta", &args, None).unwrap();
+
+        // Entonces: devuelve la consulta completa
+        assert_eq!(
+            sql.extract::<String>(py).unwrap(),
+            "SELECT *\nFROM ventas\nWHERE total > 0"
+        );
+    });
+}
+
+// ============================================================================
+// Tests de magics de IPython
+// ============================================================================
+
+#[test]
+fn test_magics_no_descartan_funciones() {
+    // Dado: funciones con escapes de shell en el cuerpo
+    let jf = JupyterFunctions::new(fixture_path("magics_notebook.ipynb"));
+
+    // Cuando: consultamos las funciones
+    let nombres = jf.functions_names();
+
+    // Entonces: se extraen, traducidas a llamadas a get_ipython()
+    assert_eq!(nombres, vec!["formato", "listar", "sumar"]);
+    let codigo = jf.get_function_code(String::from("listar")).unwrap();
+    assert!(codigo.contains("archivos = get_ipython().getoutput('ls {ruta}')"));
+    assert!(
+        jf.necessary_imports()
+            .iter()
+            .all(|i| !i.contains("get_ipython"))
+    );
+}
+
+#[test]
+fn test_magics_strip_permite_ejecutar_sin_ipython() {
+    // Dado: el notebook cargado eliminando las magics
+    let contenido = leer_fixture("magics_notebook.ipynb");
+
+    Python::attach(|py| {
+        let options = PyDict::new(py);
+        options.set_item("magics", "strip").unwrap();
+        let jf = JupyterFunctions::from_string(&contenido, "ipynb", Some(&options)).unwrap();
+
+        // Cuando: ejecutamos funciones que tenían escapes de shell
+        let suma = jf
+            .exec_function(py, "sumar", &PyTuple::new(py, [1, 2]).unwrap(), None)
+            .unwrap();
+        let texto = jf
+            .exec_function(py, "formato", &PyTuple::new(py, [3]).unwrap(), None)
+            .unwrap();
+
+        // Entonces: funcionan fuera de IPython
+        assert_eq!(suma.extract::<i64>(py).unwrap(), 3);
+        assert_eq!(texto.extract::<String>(py).unwrap(), "3 elementos");
+    });
+}
+
+#[test]
+fn test_opciones_invalidas_lanzan_excepcion() {
+    // Dado: una opción desconocida y un modo de magics desconocido
+    let contenido = leer_fixture("magics_notebook.ipynb");
+
+    Python::attach(|py| {
+        let desconocida = PyDict::new(py);
+        desconocida.set_item("magia", "strip").unwrap();
+        let modo = PyDict::new(py);
+        modo.set_item("magics", "ignorar").unwrap();
+
+        // Cuando: creamos las instancias
+        let error_opcion =
+            JupyterFunctions::from_string(&contenido, "ipynb", Some(&desconocida)).err();
+        let error_modo = JupyterFunctions::from_string(&contenido, "ipynb", Some(&modo)).err();
+
+        // Entonces: la opción desconocida es TypeError y el modo, ValueError
+        assert!(error_opcion.unwrap().is_instance_of::<PyTypeError>(py));
+        assert!(error_modo.unwrap().is_instance_of::<PyValueError>(py));
+    });
+}
+
+// ============================================================================
+// Tests de magics de celda
+// ============================================================================
+
+#[test]
+fn test_celdas_de_otros_lenguajes_se_omiten() {
+    // Dado: un notebook con celdas %%bash, %%sql, %%html y un %%writefile no Python
+    let jf = JupyterFunctions::new(fixture_path("cell_magics_notebook.ipynb"));
+
+    // Cuando: pedimos las celdas omitidas
+    let omitidas = jf.skipped_cells();
+
+    // Entonces: se informa de cada una con su magic
+    assert_eq!(
+        omitidas,
+        vec![
+            (0, String::from("bash")),
+            (1, String::from("sql")),
+            (6, String::from("html")),
+            (7, String::from("writefile")),
+        ]
+    );
+}
+
+#[test]
+fn test_capture_y_time_se_desenvuelven() {
+    // Dado: funciones en celdas %%capture y %%time
+    let jf = JupyterFunctions::new(fixture_path("cell_magics_notebook.ipynb"));
+
+    // Cuando: consultamos las funciones
+    let nombres = jf.functions_names();
+
+    // Entonces: se extraen, pero no las de los archivos escritos con %%writefile
+    assert_eq!(nombres, vec!["medida", "usar_modulo"]);
+    let location = jf.function_location(String::from("medida")).unwrap();
+    assert_eq!((location.start_line, location.end_line), (2, 3));
+}
+
+#[test]
+fn test_writefile_crea_modulos_virtuales() {
+    // Dado: un módulo escrito con %%writefile y ampliado con %%writefile -a
+    let jf = JupyterFunctions::new(fixture_path("cell_magics_notebook.ipynb"));
+
+    // Cuando: consultamos los módulos
+    let nombres = jf.modules_names();
+    let codigo = jf.module_source(String::from("utilidades")).unwrap();
+
+    // Entonces: el módulo tiene el contenido de ambas celdas
+    assert_eq!(nombres, vec!["utilidades"]);
+    assert!(codigo.starts_with("FACTOR = 3\n"));
+    assert!(codigo.contains("def cuadruplicar(x):"));
+    assert_eq!(jf.module_source(String::from("config")), None);
+}
+
+#[test]
+fn test_exec_function_importa_modulos_virtuales() {
+    // Dado: una función que importa un módulo escrito con %%writefile
+    let jf = JupyterFunctions::new(fixture_path("cell_magics_notebook.ipynb"));
+
+    Python::attach(|py| {
+        // Cuando: la ejecutamos
+        let args = PyTuple::new(py, [2]).unwrap();
+        let resultado = jf.exec_function(py, "usar_modulo", &args, None).unwrap();
+
+        // Entonces: el import encuentra el módulo virtual
+        assert_eq!(resultado.extract::<i64>(py).unwrap(), 14);
+    });
+}
+
+#[test]
+fn test_modulos_virtuales_se_importan_entre_ellos() {
+    // Dado: un módulo que importa otro que va después en orden alfabético
+    let raw = r#"{"nbformat": 4, "nbformat_minor": 5, "cells": [
+        {"cell_type": "code", "metadata": {}, "source": "%%writefile data.py\nfrom utils import twice\nfrom pkg.valores import BASE\n\nVALUE = twice(BASE)\n"},
+        {"cell_type": "code", "metadata": {}, "source": "%%writefile utils.py\ndef twice(x):\n    return 2 * x\n"},
+        {"cell_type": "code", "metadata": {}, "source": "%%writefile pkg/valores.py\nBASE = 21\n"},
+        {"cell_type": "code", "metadata": {}, "source": "def valor():\n    import data\n    return data.VALUE\n\n\ndef uno():\n    return 1\n"}
+    ]}"#;
+    let jf = JupyterFunctions::from_string(raw, "ipynb", None).unwrap();
+
+    Python::attach(|py| {
+        // Cuando: ejecutamos una función que no usa los módulos y otra que sí
+        let uno = jf
+            .exec_function(py, "uno", &PyTuple::empty(py), None)
+            .unwrap();
+        let valor = jf
+            .exec_function(py, "valor", &PyTuple::empty(py), None)
+            .unwrap();
+
+        // Entonces: los imports entre módulos se resuelven al importarlos
+        assert_eq!(uno.extract::<i64>(py).unwrap(), 1);
+        assert_eq!(valor.extract::<i64>(py).unwrap(), 42);
+    });
+}
+
+#[test]
+fn test_writefile_no_oculta_la_biblioteca_estandar() {
+    // Dado: un %%writefile con el nombre de un módulo de la biblioteca estándar
+    let raw = r#"{"nbformat": 4, "nbformat_minor": 5, "cells": [
+        {"cell_type": "code", "metadata": {}, "source": "%%writefile json.py\ndef dumps(x):\n    return 'FAKE'\n"},
+        {"cell_type": "code", "metadata": {}, "source": "import json\n\n\ndef serializar(x):\n    return json.dumps(x)\n"}
+    ]}"#;
+    let jf = JupyterFunctions::from_string(raw, "ipynb", None).unwrap();
+
+    Python::attach(|py| {
+        // Cuando: ejecutamos una función del notebook
+        let resultado = jf
+            .exec_function(py, "serializar", &PyTuple::new(py, [1]).unwrap(), None)
+            .unwrap();
+
+        // Entonces: el módulo se descarta y el json real sigue disponible en el proceso
+        assert_eq!(jf.skipped_cells(), vec![(0, String::from("writefile"))]);
+        assert!(jf.modules_names().is_empty());
+        assert_eq!(resultado.extract::<String>(py).unwrap(), "1");
+        let json = PyModule::import(py, "json").unwrap();
+        let texto = json.call_method1("dumps", (1,)).unwrap();
+        assert_eq!(texto.extract::<String>().unwrap(), "1");
+    });
+}
+
+// ============================================================================
+// Tests del modo nbdev
+// ============================================================================
+
+#[test]
+fn test_default_exp_se_expone() {
+    // Dado: un notebook nbdev cargado sin el modo nbdev
+    let jf = JupyterFunctions::new(fixture_path("nbdev_notebook.ipynb"));
+
+    // Cuando: consultamos sus funciones y su módulo de destino
+    let nombres = jf.functions_names();
+
+    // Entonces: se leen todas las celdas y el destino está disponible
+    assert_eq!(
+        nombres,
+        vec!["_ayudante", "area_circulo", "explorar", "oculta"]
+    );
+    assert_eq!(jf.default_exp(), Some(String::from("geometria")));
+}
+
+#[test]
+fn test_modo_nbdev_solo_lee_celdas_exportadas() {
+    // Dado: el mismo notebook cargado en modo nbdev
+    let contenido = leer_fixture("nbdev_notebook.ipynb");
+
+    Python::attach(|py| {
+        let options = PyDict::new(py);
+        options.set_item("nbdev", true).unwrap();
+        let jf = JupyterFunctions::from_string(&contenido, "ipynb", Some(&options)).unwrap();
+
+        // Cuando: consultamos funciones, imports y globales
+        let nombres = jf.functions_names();
+
+        // Entonces: las celdas exploratorias y ocultas no aparecen
+        assert_eq!(nombres, vec!["_ayudante", "area_circulo"]);
+        assert_eq!(jf.necessary_imports(), vec!["import math\n"]);
+        assert_eq!(jf.globals_names(), vec!["RADIO"]);
+        assert_eq!(jf.default_exp(), Some(String::from("geometria")));
+        assert!(!jf.generated_code().contains("import json"));
+
+        let area = jf
+            .exec_function(py, "area_circulo", &PyTuple::new(py, [1.0]).unwrap(), None)
+            .unwrap();
+        assert!((area.extract::<f64>(py).unwrap() - std::f64::consts::PI).abs() < 1e-9);
+    });
+}
+
+// ============================================================================
+// Tests de etiquetas de celda
+// ============================================================================
+
+#[test]
+fn test_cell_tags_devuelve_etiquetas_de_la_celda() {
+    // Dado: un notebook con celdas etiquetadas
+    let jf = JupyterFunctions::new(fixture_path("tags_notebook.ipynb"));
+
+    // Cuando / Entonces: cada función conoce las etiquetas de su celda
+    assert_eq!(
+        jf.cell_tags(String::from("doble")),
+        Some(vec![String::from("export"), String::from("test")])
+    );
+    assert_eq!(jf.cell_tags(String::from("sin_etiquetas")), Some(vec![]));
+    assert_eq!(jf.cell_tags(String::from("no_existe")), None);
+}
+
+#[test]
+fn test_exclude_tags_descarta_celdas() {
+    // Dado: un notebook con celdas scratch y test
+    let contenido = leer_fixture("tags_notebook.ipynb");
+
+    Python::attach(|py| {
+        let options = PyDict::new(py);
+        options
+            .set_item("exclude_tags", vec!["scratch", "test"])
+            .unwrap();
+
+        // Cuando: lo cargamos excluyendo esas etiquetas
+        let jf = JupyterFunctions::from_string(&contenido, "ipynb", Some(&options)).unwrap();
+
+        // Entonces: sus funciones no llegan a exec_function
+        assert_eq!(jf.functions_names(), vec!["raiz", "sin_etiquetas"]);
+        assert!(
+            jf.exec_function(py, "experimento", &PyTuple::empty(py), None)
+                .is_err()
+        );
+    });
+}
+
+#[test]
+fn test_include_tags_y_exclude_tags_combinados() {
+    // Dado: celdas export, alguna también etiquetada como test
+    let contenido = leer_fixture("tags_notebook.ipynb");
+
+    Python::attach(|py| {
+        let options = PyDict::new(py);
+        options.set_item("include_tags", vec!["export"]).unwrap();
+        options.set_item("exclude_tags", vec!["test"]).unwrap();
+
+        // Cuando: incluimos export y excluimos test
+        let jf = JupyterFunctions::from_string(&contenido, "ipynb", Some(&options)).unwrap();
+
+        // Entonces: la exclusión gana y las celdas sin etiquetas quedan fuera
+        assert_eq!(jf.functions_names(), vec!["raiz"]);
+        let raiz = jf
+            .exec_function(py, "raiz", &PyTuple::new(py, [9.0]).unwrap(), None)
+            .unwrap();
+        assert_eq!(raiz.extract::<f64>(py).unwrap(), 3.0);
+    });
+}
+
+#[test]
+fn test_function_section_devuelve_ruta_de_encabezados() {
+    // Dado: un notebook con secciones y subsecciones en markdown
+    let jf = JupyterFunctions::new(fixture_path("sections_notebook.ipynb"));
+
+    // Cuando / Entonces: cada función conoce la sección en la que está
+    assert_eq!(
+        jf.function_section(String::from("limpiar")),
+        Some(String::from("Preprocessing > Text cleaning"))
+    );
+    assert_eq!(
+        jf.function_section(String::from("cargar")),
+        Some(String::from("Preprocessing"))
+    );
+    // Un encabezado del mismo nivel cierra la subsección anterior
+    assert_eq!(
+        jf.function_section(String::from("tokenizar")),
+        Some(String::from("Preprocessing > Tokenization"))
+    );
+    assert_eq!(
+        jf.function_section(String::from("precision")),
+        Some(String::from("Evaluation"))
+    );
+    assert_eq!(
+        jf.function_section(String::from("inicio")),
+        Some(String::new())
+    );
+    assert_eq!(jf.function_section(String::from("no_existe")), None);
+}
+
+#[test]
+fn test_function_markdown_devuelve_celda_anterior() {
+    // Dado: funciones precedidas por celdas de texto
+    let jf = JupyterFunctions::new(fixture_path("sections_notebook.ipynb"));
+
+    // Cuando / Entonces: se devuelve la celda markdown más cercana
+    assert_eq!(
+        jf.function_markdown(String::from("cargar")),
+        Some(String::from(
+            "# Preprocessing\n\nFunciones para preparar los datos."
+        ))
+    );
+    assert_eq!(
+        jf.function_markdown(String::from("precision")),
+        Some(String::from("Métricas sobre el conjunto de prueba."))
+    );
+    assert_eq!(jf.function_markdown(String::from("inicio")), None);
+}
+
+#[test]
+fn test_functions_in_section_incluye_subsecciones() {
+    // Dado: un notebook con secciones anidadas
+    let jf = JupyterFunctions::new(fixture_path("sections_notebook.ipynb"));
+
+    // Cuando / Entonces: una sección incluye las funciones de sus subsecciones
+    assert_eq!(
+        jf.functions_in_section(String::from("Preprocessing")),
+        vec!["cargar", "limpiar", "normalizar", "tokenizar"]
+    );
+    assert_eq!(
+        jf.functions_in_section(String::from("Text cleaning")),
+        vec!["limpiar", "normalizar"]
+    );
+    assert_eq!(
+        jf.functions_in_section(String::from("Preprocessing > Tokenization")),
+        vec!["tokenizar"]
+    );
+    // Los comentarios dentro de bloques de código no son encabezados
+    assert!(
+        jf.functions_in_section(String::from("no es un encabezado"))
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_function_section_en_formato_percent() {
+    // Dado: un script percent con celdas markdown
+    let raw = "# %% [markdown]\n# # Utilidades\n\n# %%\ndef uno():\n    return 1\n";
+
+    // Cuando: lo cargamos
+    let jf = JupyterFunctions::from_string(raw, "percent", None).unwrap();
+
+    // Entonces: la función está en la sección del encabezado
+    assert_eq!(
+        jf.function_section(String::from("uno")),
+        Some(String::from("Utilidades"))
+    );
+    assert_eq!(
+        jf.functions_in_section(String::from("Utilidades")),
+        vec!["uno"]
+    );
+}
+
+#[test]
+fn test_function_versions_lista_las_redefiniciones() {
+    // Dado: una función definida en tres celdas
+    let jf = JupyterFunctions::new(fixture_path("versions_notebook.ipynb"));
+
+    // Cuando: pedimos sus versiones
+    let versiones = jf.function_versions(String::from("saludo"));
+
+    // Entonces: están en orden de documento y la activa es la última
+    let celdas: Vec<usize> = versiones.iter().map(|v| v.location.cell_index).collect();
+    assert_eq!(celdas, vec![0, 2, 3]);
+    let contadores: Vec<Option<u32>> = versiones.iter().map(|v| v.execution_count).collect();
+    assert_eq!(contadores, vec![Some(3), Some(1), None]);
+    let activas: Vec<bool> = versiones.iter().map(|v| v.is_active).collect();
+    assert_eq!(activas, vec![false, false, true]);
+    assert!(versiones[1].code.contains("\"v2\""));
+    assert!(jf.function_versions(String::from("no_existe")).is_empty());
+}
+
+#[test]
+fn test_redefinicion_ejecuta_solo_la_version_activa() {
+    // Dado: una función que llama a otra redefinida
+    let jf = JupyterFunctions::new(fixture_path("versions_notebook.ipynb"));
+
+    Python::attach(|py| {
+        // Cuando: la ejecutamos
+        let resultado = jf
+            .exec_function(py, "presentar", &PyTuple::new(py, ["Ana"]).unwrap(), None)
+            .unwrap();
+
+        // Entonces: usa la última versión y el código generado solo tiene esa
+        assert_eq!(resultado.extract::<String>(py).unwrap(), "v3 Ana");
+        assert_eq!(jf.generated_code().matches("def saludo").count(), 1);
+    });
+}
+
+#[test]
+fn test_resolution_execution_count() {
+    // Dado: versiones ejecutadas en distinto orden que el del documento
+    let contenido = leer_fixture("versions_notebook.ipynb");
+
+    Python::attach(|py| {
+        let options = PyDict::new(py);
+        options.set_item("resolution", "execution_count").unwrap();
+
+        // Cuando: cargamos el notebook eligiendo por execution_count
+        let jf = JupyterFunctions::from_string(&contenido, "ipynb", Some(&options)).unwrap();
+
+        // Entonces: gana la celda ejecutada la última, no la última del documento
+        let saludo = jf
+            .exec_function(py, "saludo", &PyTuple::empty(py), None)
+            .unwrap();
+        assert_eq!(saludo.extract::<String>(py).unwrap(), "v1");
+        assert_eq!(
+            jf.function_location(String::from("saludo"))
+                .unwrap()
+                .cell_index,
+            0
+        );
+    });
+}
+
+#[test]
+fn test_use_version_fija_la_celda() {
+    // Dado: una función redefinida
+    let mut jf = JupyterFunctions::new(fixture_path("versions_notebook.ipynb"));
+
+    Python::attach(|py| {
+        // Cuando: fijamos la versión de la celda 2
+        jf.use_version(String::from("saludo"), 2).unwrap();
+
+        // Entonces: es la que se ejecuta y la que devuelve get_function_code
+        let saludo = jf
+            .exec_function(py, "saludo", &PyTuple::empty(py), None)
+            .unwrap();
+        assert_eq!(saludo.extract::<String>(py).unwrap(), "v2");
+        assert!(
+            jf.get_function_code(String::from("saludo"))
+                .unwrap()
+                .contains("\"v2\"")
+        );
+        assert!(jf.function_versions(String::from("saludo"))[1].is_active);
+    });
+}
+
+#[test]
+fn test_use_version_y_resolution_invalidas() {
+    // Dado: un notebook con una función redefinida
+    let contenido = leer_fixture("versions_notebook.ipynb");
+    let mut jf = JupyterFunctions::from_string(&contenido, "ipynb", None).unwrap();
+
+    Python::attach(|py| {
+        let politica = PyDict::new(py);
+        politica.set_item("resolution", "primera").unwrap();
+
+        // Cuando / Entonces: una celda sin la función o una política desconocida son ValueError
+        let error_celda = jf.use_version(String::from("saludo"), 1).unwrap_err();
+        assert!(error_celda.is_instance_of::<PyValueError>(py));
+        assert!(jf.use_version(String::from("no_existe"), 0).is_err());
+        let error_politica =
+            JupyterFunctions::from_string(&contenido, "ipynb", Some(&politica)).err();
+        assert!(error_politica.unwrap().is_instance_of::<PyValueError>(py));
+    });
+}
@@ -51,9 +51,9 @@ fn test_funcion_contiene_cuerpo_completo() {
     let body = jf
         .functions
         .iter()
-        .find(|(n, _)| n == "factorial")
+        .find(|f| f.name == "factorial")
         .unwrap()
-        .1
+        .code
         .clone();
 
     // Entonces: debe contener toda la lógica de la función
@@ -75,9 +75,9 @@ fn test_funcion_con_docstring_preserva_formato() {
     let body = jf
         .functions
         .iter()
-        .find(|(n, _)| n == "multiplicar")
+        .find(|f| f.name == "multiplicar")
         .unwrap()
-        .1
+        .code
         .clone();
     assert!(
         body.contains("Multiplica dos números") || body.contains("\"\"\""),
@@ -118,9 +118,9 @@ fn test_funcion_closure_detecta_funcion_externa() {
     let body = jf
         .functions
         .iter()
-        .find(|(n, _)| n == "funcion_con_closure")
+        .find(|f| f.name == "funcion_con_closure")
         .unwrap()
-        .1
+        .code
         .clone();
     assert!(
         body.contains("def incrementar"),
@@ -146,16 +146,16 @@ fn test_indentacion_determina_fin_de_funcion() {
     let body1 = jf
         .functions
         .iter()
-        .find(|(n, _)| n == "funcion_con_decorador_simulado")
+        .find(|f| f.name == "funcion_con_decorador_simulado")
         .unwrap()
-        .1
+        .code
         .clone();
     let body2 = jf
         .functions
         .iter()
-        .find(|(n, _)| n == "otra_funcion_en_misma_celda")
+        .find(|f| f.name == "otra_funcion_en_misma_celda")
         .unwrap()
-        .1
+        .code
         .clone();
     assert_ne!(body1, body2, "Los cuerpos deben ser diferentes");
 }
@@ -173,9 +173,9 @@ fn test_caracteres_especiales_en_strings_python() {
     let body = jf
         .functions
         .iter()
-        .find(|(n, _)| n == "funcion_con_string_especial")
+        .find(|f| f.name == "funcion_con_string_especial")
         .unwrap()
-        .1
+        .code
         .clone();
     // Verificamos que el código es válido (tiene la estructura esperada)
     assert!(body.contains("texto"), "Debe contener la variable 'texto'");
@@ -194,9 +194,9 @@ fn test_funcion_vacia_con_pass() {
     let body = jf
         .functions
         .iter()
-        .find(|(n, _)| n == "funcion_vacia")
+        .find(|f| f.name == "funcion_vacia")
         .unwrap()
-        .1
+        .code
         .clone();
     assert!(body.contains("pass"), "Debe contener 'pass'");
 }
@@ -214,9 +214,9 @@ fn test_try_except_preserva_estructura() {
     let body = jf
         .functions
         .iter()
-        .find(|(n, _)| n == "funcion_con_try_except")
+        .find(|f| f.name == "funcion_con_try_except")
         .unwrap()
-        .1
+        .code
         .clone();
     assert!(body.contains("try"), "Debe contener 'try'");
     assert!(body.contains("except"), "Debe contener 'except'");
@@ -236,9 +236,9 @@ fn test_parametros_con_valores_default() {
     let body = jf
         .functions
         .iter()
-        .find(|(n, _)| n == "saludar")
+        .find(|f| f.name == "saludar")
         .unwrap()
-        .1
+        .code
         .clone();
     assert!(
         body.contains("saludo=") || body.contains("saludo ="),
@@ -259,9 +259,9 @@ fn test_lambda_dentro_de_funcion() {
     let body = jf
         .functions
         .iter()
-        .find(|(n, _)| n == "funcion_con_lambda")
+        .find(|f| f.name == "funcion_con_lambda")
         .unwrap()
-        .1
+        .code
         .clone();
     assert!(body.contains("lambda"), "Debe contener la expresión lambda");
 }
@@ -347,7 +347,7 @@ fn test_hashmap_funciones_permite_acceso_por_nombre() {
     // Entonces: cada nombre debe existir en el Vec interno
     for nombre in &nombres {
         assert!(
-            jf.functions.iter().any(|(n, _)| n == nombre),
+            jf.functions.iter().any(|f| &f.name == nombre),
             "El nombre '{}' debe existir en el Vec",
             nombre
         );
@@ -385,9 +385,9 @@ fn test_funcion_multilinea_parametros() {
     let body = jf
         .functions
         .iter()
-        .find(|(n, _)| n == "funcion_multilinea")
+        .find(|f| f.name == "funcion_multilinea")
         .unwrap()
-        .1
+        .code
         .clone();
     assert!(body.contains("param1"), "Debe contener param1");
     assert!(body.contains("param2"), "Debe contener param2");
@@ -401,13 +401,13 @@ fn test_estructura_cuerpo_funcion_termina_con_newline() {
     let jf = JupyterFunctions::new(path);
 
     // Cuando: verificamos los cuerpos de las funciones
-    for (nombre, body) in &jf.functions {
+    for funcion in &jf.functions {
         // Entonces: cada cuerpo debe terminar con newline
         // (conocemos que process_code agrega \n al final de cada línea)
         assert!(
-            body.ends_with('\n'),
+            funcion.code.ends_with('\n'),
             "El cuerpo de '{}' debe terminar con newline",
-            nombre
+            funcion.name
         );
     }
 }
@@ -419,12 +419,12 @@ fn test_cuerpo_funcion_empieza_con_def() {
     let jf = JupyterFunctions::new(path);
 
     // Cuando: verificamos los cuerpos de las funciones
-    for (nombre, body) in &jf.functions {
+    for funcion in &jf.functions {
         // Entonces: cada cuerpo debe empezar con 'def'
         assert!(
-            body.starts_with("def"),
+            funcion.code.starts_with("def"),
             "El cuerpo de '{}' debe empezar con 'def'",
-            nombre
+            funcion.name
         );
     }
 }
@@ -472,3 +472,88 @@ fn test_import_regex_requiere_espacio_despues() {
         "No debe capturar 'import' como parte de otros identificadores"
     );
 }
+
+// ============================================================================
+// Tests de procedencia conociendo la celda de origen
+// ============================================================================
+
+#[test]
+fn test_location_indice_cuenta_celdas_markdown() {
+    // Dado: un notebook con una celda markdown antes de la última función
+    let jf = JupyterFunctions::new(fixture_path("complex_notebook.ipynb"));
+
+    // Cuando: buscamos la función que va después del markdown
+    let funcion = jf
+        .functions
+        .iter()
+        .find(|f| f.name == "funcion_despues_markdown")
+        .unwrap();
+
+    // Entonces: el índice es el de la celda en el notebook (no entre las de código)
+    assert_eq!(funcion.location.cell_index, 9);
+}
+
+#[test]
+fn test_location_excluye_lineas_vacias_finales() {
+    // Dado: una celda con dos funciones separadas por una línea vacía
+    let jf = JupyterFunctions::new(fixture_path("complex_notebook.ipynb"));
+
+    // Cuando: buscamos la primera función
+    let funcion = jf
+        .functions
+        .iter()
+        .find(|f| f.name == "funcion_con_decorador_simulado")
+        .unwrap();
+
+    // Entonces: la última línea es la del cuerpo, no la línea vacía
+    assert_eq!(
+        (funcion.location.start_line, funcion.location.end_line),
+        (1, 3)
+    );
+}
+
+// ============================================================================
+// Tests de decoradores conociendo el código generado
+// ============================================================================
+
+#[test]
+fn test_decorador_del_notebook_se_define_antes() {
+    // Dado: un decorador definido en una celda posterior a su uso
+    let jf = JupyterFunctions::new(fixture_path("decorators_notebook.ipynb"));
+
+    // Cuando: generamos el código completo
+    let codigo = jf.generated_code();
+
+    // Entonces: el decorador debe definirse antes que la función decorada
+    let registrar = codigo.find("def registrar").unwrap();
+    let saludar = codigo.find("@registrar(").unwrap();
+    assert!(registrar < saludar);
+}
+
+#[test]
+fn test_location_empieza_en_el_decorador() {
+    // Dado: una función con un decorador de tres líneas
+    let jf = JupyterFunctions::new(fixture_path("decorators_notebook.ipynb"));
+
+    // Cuando: pedimos su ubicación
+    let location = jf.function_location(String::from("saludar")).unwrap();
+
+    // Entonces: abarca desde el decorador hasta el final del cuerpo
+    assert_eq!((location.start_line, location.end_line), (1, 5));
+}
+
+#[test]
+fn test_globales_se_definen_antes_que_las_funciones() {
+    // Dado: constantes y funciones en celdas distintas
+    let jf = JupyterFunctions::new(fixture_path("globals_notebook.ipynb"));
+
+    // Cuando: generamos el código
+    let codigo = jf.generated_code();
+
+    // Entonces: las globales van tras los imports y antes de las funciones
+    let import = codigo.find("import math").unwrap();
+    let threshold = codigo.find("THRESHOLD = 0.8").unwrap();
+    let funcion = codigo.find("def clasificar").unwrap();
+    assert!(import < threshold && threshold < funcion);
+    assert!(!codigo.contains("resultado ="));
+}
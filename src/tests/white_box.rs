@@ -12,7 +12,17 @@
 //! Requieren conocimiento detallado de la implementación.
 
 use super::fixture_path;
-use crate::{JupyterFunctions, clean_line_json, imports_as_lines, process_code};
+use crate::archive::split_member;
+use crate::encoding::{decode, normalize};
+use crate::magics::{CellMagic, MagicsMode, cell_magic, module_name, transform};
+use crate::nbdev::{default_exp, directives, is_exported};
+use crate::sections::{contexts, headings};
+use crate::syntax::{StatementKind, parse_cell};
+use crate::{
+    CellType, JupyterFunctions, Notebook, NotebookError, NotebookFormat, Resolution,
+    clean_line_json, extract_classes, extract_functions, extract_globals, imports_as_lines,
+    process_code, process_lines,
+};
 use std::collections::HashSet;
 
 // ============================================================================
@@ -367,6 +377,7 @@ fn test_imports_as_lines_sin_imports() {
     let jf = JupyterFunctions {
         functions: Vec::new(),
         imports: HashSet::new(),
+        ..Default::default()
     };
 
     // Cuando: generamos las líneas de imports
@@ -523,3 +534,1114 @@ fn test_regex_funcion_nombre_con_numeros() {
     // Entonces: debe capturar el nombre
     assert!(functions.iter().any(|(n, _)| n == "funcion123"));
 }
+
+// ============================================================================
+// Tests del modelo Notebook
+// ============================================================================
+
+#[test]
+fn test_notebook_from_json_lee_celdas() {
+    // Dado: un notebook JSON mínimo con una celda de código y una markdown
+    let raw = r##"{"cells": [
+        {"cell_type": "markdown", "metadata": {}, "source": ["# Titulo"]},
+        {"cell_type": "code", "metadata": {}, "outputs": [], "source": ["x = 1\n", "y = 2"]}
+    ], "metadata": {}, "nbformat": 4, "nbformat_minor": 5}"##;
+
+    // Cuando: lo parseamos
+    let notebook = Notebook::from_json(raw).unwrap();
+
+    // Entonces: debe conservar el tipo de cada celda y la versión
+    assert_eq!(notebook.nbformat, Some((4, 5)));
+    assert_eq!(notebook.cells.len(), 2);
+    assert_eq!(notebook.cells[0].cell_type, CellType::Markdown);
+    assert_eq!(notebook.code_cells().count(), 1);
+}
+
+#[test]
+fn test_cell_lines_une_fragmentos_del_source() {
+    // Dado: una celda cuyo source parte líneas en varios fragmentos
+    let raw = r#"{"cells": [{"cell_type": "code", "source": ["def f(", "):\n", "    return 1\n"]}],
+        "nbformat": 4}"#;
+    let notebook = Notebook::from_json(raw).unwrap();
+
+    // Cuando: obtenemos las líneas lógicas
+    let lines = notebook.cells[0].lines();
+
+    // Entonces: deben reconstruirse sin saltos de línea finales
+    assert_eq!(lines, vec!["def f():", "    return 1"]);
+}
+
+#[test]
+fn test_notebook_from_json_invalido() {
+    // Dado: un texto que no es JSON
+    let raw = "\"cell_type\": \"code\"";
+
+    // Cuando: intentamos parsearlo
+    let resultado = Notebook::from_json(raw);
+
+    // Entonces: debe devolver error
+    assert!(resultado.is_err());
+}
+
+#[test]
+fn test_process_lines_sin_entrecomillado_json() {
+    // Dado: líneas lógicas de una celda (sin comillas JSON)
+    let mut functions: Vec<(String, String)> = Vec::new();
+    let mut imports = HashSet::new();
+    let lines = vec![
+        String::from("import os"),
+        String::from("def ruta():"),
+        String::from("    return os.sep"),
+    ];
+
+    // Cuando: procesamos las líneas
+    process_lines(&mut functions, &mut imports, lines);
+
+    // Entonces: debe extraer el import y la función
+    assert!(imports.contains("import os\n"));
+    assert!(functions.iter().any(|(n, _)| n == "ruta"));
+}
+
+#[test]
+fn test_notebook_v3_normaliza_heading_a_markdown() {
+    // Dado: un notebook v3 con una celda heading de nivel 2
+    let raw = r#"{"nbformat": 3, "nbformat_minor": 0, "worksheets": [{"cells": [
+        {"cell_type": "heading", "level": 2, "source": ["Preprocesado"]},
+        {"cell_type": "code", "input": ["x = 1"], "language": "python"}
+    ]}]}"#;
+
+    // Cuando: lo parseamos
+    let notebook = Notebook::from_json(raw).unwrap();
+
+    // Entonces: el heading pasa a markdown y el input a source
+    assert_eq!(notebook.cells[0].cell_type, CellType::Markdown);
+    assert_eq!(notebook.cells[0].lines(), vec!["## Preprocesado"]);
+    assert_eq!(notebook.cells[1].lines(), vec!["x = 1"]);
+}
+
+#[test]
+fn test_cell_source_como_string_unico() {
+    // Dado: la misma celda con source en forma de string y en forma de lista
+    let raw = r#"{"nbformat": 4, "nbformat_minor": 5, "cells": [
+        {"cell_type": "code", "source": "def f(x):\n    return x\n"},
+        {"cell_type": "code", "source": ["def f(x):\n", "    return x\n"]}
+    ]}"#;
+
+    // Cuando: lo parseamos
+    let notebook = Notebook::from_json(raw).unwrap();
+
+    // Entonces: ambas formas deben dar las mismas líneas lógicas
+    assert_eq!(notebook.cells[0].lines(), notebook.cells[1].lines());
+    assert_eq!(notebook.cells[0].lines(), vec!["def f(x):", "    return x"]);
+}
+
+// ============================================================================
+// Tests del formato percent
+// ============================================================================
+
+#[test]
+fn test_percent_separa_celdas_por_marcador() {
+    // Dado: un script percent con código antes del primer marcador
+    let raw =
+        "x = 1\n\n# %%\ny = 2\n\n# %% [markdown]\n# # Titulo\n#\n# texto\n\n# %% [raw]\n# crudo\n";
+
+    // Cuando: lo parseamos
+    let notebook = Notebook::parse(raw, NotebookFormat::Percent).unwrap();
+
+    // Entonces: cada marcador abre una celda del tipo indicado
+    let tipos: Vec<CellType> = notebook.cells.iter().map(|c| c.cell_type.clone()).collect();
+    assert_eq!(
+        tipos,
+        vec![
+            CellType::Code,
+            CellType::Code,
+            CellType::Markdown,
+            CellType::Raw
+        ]
+    );
+    assert_eq!(notebook.cells[1].lines(), vec!["y = 2"]);
+    assert_eq!(notebook.cells[2].lines(), vec!["# Titulo", "", "texto"]);
+}
+
+#[test]
+fn test_percent_descarta_cabecera_jupytext() {
+    // Dado: un script percent con cabecera YAML
+    let raw =
+        "# ---\n# jupyter:\n#   jupytext:\n#     format_name: percent\n# ---\n\n# %%\nimport os\n";
+
+    // Cuando: lo parseamos
+    let notebook = Notebook::parse(raw, NotebookFormat::Percent).unwrap();
+
+    // Entonces: la cabecera no debe aparecer como celda
+    assert_eq!(notebook.cells.len(), 1);
+    assert_eq!(notebook.cells[0].lines(), vec!["import os"]);
+}
+
+#[test]
+fn test_notebook_format_from_path() {
+    // Dado / Cuando / Entonces: la extensión decide el formato
+    assert_eq!(NotebookFormat::from_path("a/b.py"), NotebookFormat::Percent);
+    assert_eq!(NotebookFormat::from_path("a/b.PY"), NotebookFormat::Percent);
+    assert_eq!(
+        NotebookFormat::from_path("a/b.ipynb"),
+        NotebookFormat::Ipynb
+    );
+}
+
+// ============================================================================
+// Tests del formato Markdown
+// ============================================================================
+
+#[test]
+fn test_markdown_texto_y_bloques_ajenos_quedan_en_markdown() {
+    // Dado: un documento con prosa, un bloque bash y un bloque python
+    let raw = "# Titulo\n\n```bash\nls\n```\n\n```python\nx = 1\n```\n\nFin\n";
+
+    // Cuando: lo parseamos
+    let notebook = Notebook::parse(raw, NotebookFormat::Markdown).unwrap();
+
+    // Entonces: solo el bloque python es una celda de código
+    let tipos: Vec<CellType> = notebook.cells.iter().map(|c| c.cell_type.clone()).collect();
+    assert_eq!(
+        tipos,
+        vec![CellType::Markdown, CellType::Code, CellType::Markdown]
+    );
+    assert_eq!(
+        notebook.cells[0].lines(),
+        vec!["# Titulo", "", "```bash", "ls", "```"]
+    );
+    assert_eq!(notebook.cells[1].lines(), vec!["x = 1"]);
+}
+
+#[test]
+fn test_markdown_bloque_sin_cerrar_llega_al_final() {
+    // Dado: un bloque python sin cierre
+    let raw = "```python\ndef f():\n    return 1\n";
+
+    // Cuando: lo parseamos
+    let notebook = Notebook::parse(raw, NotebookFormat::Markdown).unwrap();
+
+    // Entonces: el bloque abarca hasta el final del documento
+    assert_eq!(notebook.cells.len(), 1);
+    assert_eq!(notebook.cells[0].lines(), vec!["def f():", "    return 1"]);
+}
+
+#[test]
+fn test_notebook_format_from_path_markdown() {
+    // Dado / Cuando / Entonces: las extensiones markdown se reconocen
+    for ruta in ["a.md", "a.qmd", "a.Rmd", "a.markdown"] {
+        assert_eq!(NotebookFormat::from_path(ruta), NotebookFormat::Markdown);
+    }
+}
+
+// ============================================================================
+// Tests de lectura de archivos
+// ============================================================================
+
+#[test]
+fn test_split_member_solo_con_archivo_contenedor() {
+    // Dado / Cuando / Entonces: el separador solo cuenta tras un .zip/.tar
+    assert_eq!(
+        split_member("datos/a.zip!nb/x.ipynb"),
+        Some(("datos/a.zip", "nb/x.ipynb"))
+    );
+    assert_eq!(
+        split_member("a.tar.gz!x.ipynb"),
+        Some(("a.tar.gz", "x.ipynb"))
+    );
+    assert_eq!(split_member("carpeta!rara/x.ipynb"), None);
+}
+
+#[test]
+fn test_notebook_format_from_path_ignora_gz() {
+    // Dado / Cuando / Entonces: el sufijo .gz no cambia el formato
+    assert_eq!(
+        NotebookFormat::from_path("x.ipynb.gz"),
+        NotebookFormat::Ipynb
+    );
+    assert_eq!(
+        NotebookFormat::from_path("x.py.gz"),
+        NotebookFormat::Percent
+    );
+}
+
+// ============================================================================
+// Tests del formato Databricks
+// ============================================================================
+
+#[test]
+fn test_databricks_tipos_de_celda_magic() {
+    // Dado: un notebook Databricks con celdas %md, %sql y código
+    let raw = "# Databricks notebook source\nx = 1\n\n# COMMAND ----------\n\n# MAGIC %md\n# MAGIC # Titulo\n\n# COMMAND ----------\n\n# MAGIC %sql\n# MAGIC SELECT 1\n";
+
+    // Cuando: lo parseamos como .py
+    let notebook = Notebook::parse(raw, NotebookFormat::Percent).unwrap();
+
+    // Entonces: el contenido decide el formato y cada MAGIC su tipo
+    let tipos: Vec<CellType> = notebook.cells.iter().map(|c| c.cell_type.clone()).collect();
+    assert_eq!(
+        tipos,
+        vec![CellType::Code, CellType::Markdown, CellType::Raw]
+    );
+    assert_eq!(notebook.cells[1].lines(), vec!["# Titulo"]);
+    assert_eq!(notebook.cells[2].lines(), vec!["%sql", "SELECT 1"]);
+}
+
+#[test]
+fn test_databricks_magic_con_espacios_extra() {
+    // Dado: un `%md` con dos espacios tras `# MAGIC`
+    let raw = "# Databricks notebook source\n# MAGIC  %md Resumen\n# MAGIC Texto\n";
+
+    // Cuando: lo parseamos
+    let notebook = Notebook::parse(raw, NotebookFormat::Databricks).unwrap();
+
+    // Entonces: el comando se quita entero
+    assert_eq!(notebook.cells[0].cell_type, CellType::Markdown);
+    assert_eq!(notebook.cells[0].lines(), vec!["Resumen", "Texto"]);
+}
+
+#[test]
+fn test_detect_solo_cambia_py_con_cabecera_databricks() {
+    // Dado / Cuando / Entonces: la cabecera distingue Databricks de percent
+    let databricks = "# Databricks notebook source\nx = 1\n";
+    assert_eq!(
+        NotebookFormat::Percent.detect(databricks),
+        NotebookFormat::Databricks
+    );
+    assert_eq!(
+        NotebookFormat::Percent.detect("# %%\nx = 1\n"),
+        NotebookFormat::Percent
+    );
+    assert_eq!(
+        NotebookFormat::Ipynb.detect(databricks),
+        NotebookFormat::Ipynb
+    );
+}
+
+// ============================================================================
+// Tests del formato marimo
+// ============================================================================
+
+#[test]
+fn test_marimo_celdas_sin_return_y_markdown() {
+    // Dado: un notebook marimo con una celda de código y otra de mo.md
+    let raw = "import marimo\napp = marimo.App()\n\n@app.cell\ndef _(np):\n    x = np.zeros(3)\n    return (x,)\n\n@app.cell\ndef _(mo):\n    mo.md(\"\"\"# Titulo\"\"\")\n    return\n";
+
+    // Cuando: lo parseamos como .py
+    let notebook = Notebook::parse(raw, NotebookFormat::Percent).unwrap();
+
+    // Entonces: el cuerpo se desindenta sin su return y mo.md pasa a markdown
+    assert_eq!(notebook.cells.len(), 2);
+    assert_eq!(notebook.cells[0].cell_type, CellType::Code);
+    assert_eq!(notebook.cells[0].lines(), vec!["x = np.zeros(3)"]);
+    assert_eq!(notebook.cells[1].cell_type, CellType::Markdown);
+    assert_eq!(notebook.cells[1].lines(), vec!["# Titulo"]);
+}
+
+// ============================================================================
+// Tests de decodificación y normalización
+// ============================================================================
+
+#[test]
+fn test_clean_line_json_quita_crlf_escapado() {
+    // Dado: una línea JSON cuyo contenido termina en \r\n
+    let linea = String::from("    \"def f():\\r\\n\",");
+
+    // Cuando: limpiamos la línea
+    let resultado = clean_line_json(linea);
+
+    // Entonces: no debe quedar un \r suelto
+    assert_eq!(resultado, "def f():");
+}
+
+#[test]
+fn test_decode_quita_bom() {
+    // Dado: bytes UTF-8 con BOM
+    let bytes = b"\xEF\xBB\xBFx = 1";
+
+    // Cuando / Entonces: el BOM no forma parte del texto
+    assert_eq!(decode(bytes).unwrap(), "x = 1");
+}
+
+#[test]
+fn test_decode_offset_del_primer_byte_invalido() {
+    // Dado: bytes con un byte inválido en la posición 3
+    let bytes = b"abc\xFFdef";
+
+    // Cuando / Entonces: el error indica esa posición
+    assert!(matches!(
+        decode(bytes),
+        Err(NotebookError::Decode { offset: 3 })
+    ));
+}
+
+#[test]
+fn test_normalize_crlf_y_bom() {
+    // Dado / Cuando / Entonces: CRLF pasa a LF y el BOM desaparece
+    assert_eq!(normalize("\u{feff}a\r\nb\r\n"), "a\nb\n");
+}
+
+#[test]
+fn test_cr_suelto_es_salto_de_linea() {
+    // Dado: una celda con un CR suelto dentro de un comentario, como lo ve Python
+    let raw = r#"{"nbformat": 4, "nbformat_minor": 5, "cells": [
+        {"cell_type": "code", "metadata": {}, "source": "x = 1 # a\rb = 2\n@staticmethod\ndef f():\n    return 1\n"}
+    ]}"#;
+    let notebook = Notebook::from_json(raw).unwrap();
+
+    // Cuando: obtenemos sus líneas y la extraemos
+    let lines = notebook.cells[0].lines();
+    let jf = JupyterFunctions::from_notebook(&notebook, &Default::default());
+
+    // Entonces: el CR parte la línea y las posiciones del ast no se salen de la celda
+    assert_eq!(lines[1], "b = 2");
+    assert_eq!(normalize("a\rb"), "a\nb");
+    assert_eq!(
+        jf.decorators(String::from("f")),
+        Some(vec![String::from("staticmethod")])
+    );
+}
+
+#[test]
+fn test_parse_cell_con_cr_no_hace_panic() {
+    // Dado: líneas que aún contienen un CR suelto (llamada directa, sin normalizar)
+    let lines = vec![
+        String::from("x = 1 # a\rb"),
+        String::from("@staticmethod"),
+        String::from("def f(): pass"),
+    ];
+
+    // Cuando / Entonces: las sentencias con posiciones fuera de las líneas se descartan
+    let statements = parse_cell(&lines);
+    assert!(statements.iter().all(|s| s.end < lines.len()));
+}
+
+// ============================================================================
+// Tests de imports multilínea
+// ============================================================================
+
+#[test]
+fn test_process_lines_import_entre_parentesis() {
+    // Dado: un import con lista entre paréntesis seguido de una función
+    let mut functions: Vec<(String, String)> = Vec::new();
+    let mut imports = HashSet::new();
+    let lines: Vec<String> = vec![
+        "from sklearn.metrics import (",
+        "    accuracy_score,",
+        "    f1_score,",
+        ")",
+        "def puntuar(y, p):",
+        "    return f1_score(y, p)",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect();
+
+    // Cuando: procesamos las líneas
+    process_lines(&mut functions, &mut imports, lines);
+
+    // Entonces: el import se guarda entero y la función se sigue detectando
+    assert!(
+        imports.contains("from sklearn.metrics import (\n    accuracy_score,\n    f1_score,\n)\n")
+    );
+    assert!(functions.iter().any(|(n, _)| n == "puntuar"));
+}
+
+#[test]
+fn test_process_lines_import_con_barra_invertida() {
+    // Dado: un import que continúa con `\`
+    let mut functions: Vec<(String, String)> = Vec::new();
+    let mut imports = HashSet::new();
+    let lines = vec![
+        String::from("import os, \\"),
+        String::from("    sys"),
+        String::from("import json"),
+    ];
+
+    // Cuando: procesamos las líneas
+    process_lines(&mut functions, &mut imports, lines);
+
+    // Entonces: la continuación forma parte del mismo import
+    assert_eq!(imports.len(), 2);
+    assert!(imports.contains("import os, \\\n    sys\n"));
+    assert!(imports.contains("import json\n"));
+}
+
+#[test]
+fn test_process_lines_try_sin_imports_no_es_import() {
+    // Dado: un try de nivel raíz que no contiene imports
+    let mut functions: Vec<(String, String)> = Vec::new();
+    let mut imports = HashSet::new();
+    let lines = vec![
+        String::from("try:"),
+        String::from("    x = 1 / 0"),
+        String::from("except ZeroDivisionError:"),
+        String::from("    x = 0"),
+    ];
+
+    // Cuando: procesamos las líneas
+    process_lines(&mut functions, &mut imports, lines);
+
+    // Entonces: no debe tratarse como import
+    assert!(imports.is_empty());
+}
+
+#[test]
+fn test_imports_as_lines_simples_antes_que_protegidos() {
+    // Dado: un import protegido que depende de un import simple
+    let mut imports = HashSet::new();
+    imports.insert(String::from("if sys.platform:\n    import os\n"));
+    imports.insert(String::from("import sys\n"));
+    let jf = JupyterFunctions {
+        imports,
+        ..Default::default()
+    };
+
+    // Cuando: generamos las líneas de imports
+    let resultado = imports_as_lines(&jf);
+
+    // Entonces: el import simple debe ir primero
+    assert!(resultado.starts_with("import sys\n"));
+}
+
+#[test]
+fn test_extract_functions_marca_async_def() {
+    // Dado: una corrutina y una función normal en la misma celda
+    let mut imports = HashSet::new();
+    let lines = vec![
+        String::from("async def leer(url):"),
+        String::from("    return url"),
+        String::from(""),
+        String::from("def normal():"),
+        String::from("    return 1"),
+    ];
+
+    // Cuando: extraemos las funciones
+    let funciones = extract_functions(&mut imports, &lines);
+
+    // Entonces: ambas se extraen y solo la primera queda marcada como async
+    let resumen: Vec<(&str, bool)> = funciones
+        .iter()
+        .map(|f| (f.name.as_str(), f.is_async))
+        .collect();
+    assert_eq!(resumen, vec![("leer", true), ("normal", false)]);
+    assert!(funciones[0].code.starts_with("async def leer(url):"));
+}
+
+#[test]
+fn test_extract_classes_con_cabecera_en_varias_lineas() {
+    // Dado: una clase decorada cuyas bases ocupan varias líneas
+    let lines = vec![
+        String::from("@registrar"),
+        String::from("class Hija("),
+        String::from("    Base,"),
+        String::from("):"),
+        String::from("    def metodo(self):"),
+        String::from("        return 1"),
+        String::from(""),
+        String::from("x = Hija()"),
+    ];
+
+    // Cuando: extraemos las clases
+    let clases = extract_classes(&lines);
+
+    // Entonces: el bloque llega hasta el último método y conserva el decorador
+    assert_eq!(clases.len(), 1);
+    assert_eq!(clases[0].name, "Hija");
+    assert_eq!(clases[0].decorators, vec!["registrar"]);
+    assert_eq!(
+        (clases[0].location.start_line, clases[0].location.end_line),
+        (1, 6)
+    );
+    assert!(!clases[0].code.contains("x = Hija()"));
+}
+
+#[test]
+fn test_extract_functions_ignora_metodos_de_clase() {
+    // Dado: una clase con métodos
+    let mut imports = HashSet::new();
+    let lines = vec![
+        String::from("class Punto:"),
+        String::from("    def norma(self):"),
+        String::from("        return 0"),
+    ];
+
+    // Cuando: extraemos las funciones
+    let funciones = extract_functions(&mut imports, &lines);
+
+    // Entonces: los métodos no son funciones de nivel raíz
+    assert!(funciones.is_empty());
+}
+
+#[test]
+fn test_extract_globals_solo_acepta_nombres_conocidos() {
+    // Dado: asignaciones que leen nombres conocidos y desconocidos
+    let mut known: HashSet<String> = ["math", "range"].iter().map(|s| s.to_string()).collect();
+    let lines = vec![
+        String::from("RADIO = 2"),
+        String::from("AREA = math.pi * RADIO ** 2"),
+        String::from("modelo = entrenar(AREA)"),
+        String::from("PARES = [i for i in range(RADIO)]"),
+        String::from("datos.columna = 1"),
+        String::from("if RADIO == 2:"),
+        String::from("    pass"),
+    ];
+
+    // Cuando: extraemos las globales
+    let globales = extract_globals(&lines, &mut known);
+
+    // Entonces: solo quedan las que se pueden evaluar sin el resto del notebook
+    let nombres: Vec<&str> = globales.iter().map(|g| g.names[0].as_str()).collect();
+    assert_eq!(nombres, vec!["RADIO", "AREA", "PARES"]);
+    assert!(known.contains("PARES"));
+    assert!(!known.contains("modelo"));
+}
+
+#[test]
+fn test_extract_globals_descarta_llamadas_con_efectos() {
+    // Dado: asignaciones con llamadas que hacen E/S y con constructores puros
+    let mut known: HashSet<String> = ["requests", "pd", "open", "input", "Path", "re"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let lines = vec![
+        String::from("URL = \"https://example.com\""),
+        String::from("resp = requests.get(URL)"),
+        String::from("df = pd.read_csv(\"big.csv\")"),
+        String::from("fh = open(URL)"),
+        String::from("name = input(\"?\")"),
+        String::from("RAIZ = Path(\"/data\") / \"raw\""),
+        String::from("PATRON = re.compile(r\"\\d+\")"),
+        String::from("LECTOR = pd.read_csv"),
+    ];
+
+    // Cuando: extraemos las globales
+    let globales = extract_globals(&lines, &mut known);
+
+    // Entonces: solo quedan las que no ejecutan llamadas fuera de la lista permitida
+    let nombres: Vec<&str> = globales.iter().map(|g| g.names[0].as_str()).collect();
+    assert_eq!(nombres, vec!["URL", "RAIZ", "PATRON", "LECTOR"]);
+}
+
+#[test]
+fn test_extract_globals_descarta_indices_que_pueden_fallar() {
+    // Dado: índices sobre variables de entorno, globales, literales y tipos genéricos
+    let mut known: HashSet<String> = ["os", "typing", "Optional", "list", "float", "str", "int"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let lines = vec![
+        String::from("CONFIG = {\"a\": 1}"),
+        String::from("HOME_X = os.environ[\"NOPE_VAR_XYZ\"]"),
+        String::from("A = CONFIG[\"a\"]"),
+        String::from("PRIMERO = (1, 2)[0]"),
+        String::from("Vector = list[float]"),
+        String::from("Nombre = Optional[str]"),
+        String::from("Pares = typing.Dict[str, int]"),
+    ];
+
+    // Cuando: extraemos las globales
+    let globales = extract_globals(&lines, &mut known);
+
+    // Entonces: solo se indexan literales y tipos genéricos
+    let nombres: Vec<&str> = globales.iter().map(|g| g.names[0].as_str()).collect();
+    assert_eq!(
+        nombres,
+        vec!["CONFIG", "PRIMERO", "Vector", "Nombre", "Pares"]
+    );
+}
+
+#[test]
+fn test_extract_globals_asignacion_en_varias_lineas() {
+    // Dado: una lista que ocupa varias líneas y una asignación múltiple
+    let mut known = HashSet::new();
+    let lines = vec![
+        String::from("ETIQUETAS = ["),
+        String::from("    \"a\","),
+        String::from("]"),
+        String::from("ANCHO, ALTO = 3, 4"),
+    ];
+
+    // Cuando: extraemos las globales
+    let globales = extract_globals(&lines, &mut known);
+
+    // Entonces: la primera abarca tres líneas y la segunda asigna dos nombres
+    assert_eq!(globales[0].code, "ETIQUETAS = [\n    \"a\",\n]\n");
+    assert_eq!(
+        (
+            globales[0].location.start_line,
+            globales[0].location.end_line
+        ),
+        (1, 3)
+    );
+    assert_eq!(globales[1].names, vec!["ANCHO", "ALTO"]);
+}
+
+#[test]
+fn test_extract_functions_lambdas_y_partials() {
+    // Dado: una celda con un def, una lambda y un partial en varias líneas
+    let mut imports = HashSet::new();
+    let lines = vec![
+        String::from("doble = lambda x: x * 2"),
+        String::from("def base(a, b):"),
+        String::from("    return a - b"),
+        String::from("restar_uno = partial("),
+        String::from("    base,"),
+        String::from("    b=1,"),
+        String::from(")"),
+        String::from("valor = base(1, 2)"),
+    ];
+
+    // Cuando: extraemos las funciones
+    let funciones = extract_functions(&mut imports, &lines);
+
+    // Entonces: aparecen en orden de documento y las asignaciones quedan marcadas
+    let resumen: Vec<(&str, bool)> = funciones
+        .iter()
+        .map(|f| (f.name.as_str(), f.is_assignment))
+        .collect();
+    assert_eq!(
+        resumen,
+        vec![("doble", true), ("base", false), ("restar_uno", true)]
+    );
+    assert_eq!(
+        funciones[2].code,
+        "restar_uno = partial(\n    base,\n    b=1,\n)\n"
+    );
+    assert_eq!(
+        (
+            funciones[2].location.start_line,
+            funciones[2].location.end_line
+        ),
+        (4, 7)
+    );
+}
+
+#[test]
+fn test_extract_globals_ignora_lambdas_y_partials() {
+    // Dado: asignaciones de callables que solo usan nombres conocidos
+    let mut known: HashSet<String> = ["functools", "max"].iter().map(|s| s.to_string()).collect();
+    let lines = vec![
+        String::from("mayor = lambda xs: max(xs)"),
+        String::from("maximo = functools.partial(max, default=0)"),
+    ];
+
+    // Cuando: extraemos las globales
+    let globales = extract_globals(&lines, &mut known);
+
+    // Entonces: no son globales (se extraen como funciones)
+    assert!(globales.is_empty());
+}
+
+#[test]
+fn test_parse_cell_limites_segun_el_ast() {
+    // Dado: una función con un docstring cuyas líneas están en la columna 0
+    let lines = vec![
+        String::from("def f():"),
+        String::from("    \"\"\"Resumen."),
+        String::from(""),
+        String::from("def falsa(): no es código"),
+        String::from("\"\"\""),
+        String::from("    return 1"),
+        String::from("x = 2"),
+    ];
+
+    // Cuando: analizamos la celda
+    let sentencias = parse_cell(&lines);
+
+    // Entonces: la función acaba en su return y el texto del docstring no es una función
+    assert_eq!(sentencias.len(), 2);
+    assert_eq!(
+        sentencias[0].kind,
+        StatementKind::Function {
+            name: String::from("f"),
+            is_async: false
+        }
+    );
+    assert_eq!((sentencias[0].start, sentencias[0].end), (0, 5));
+    assert_eq!((sentencias[1].start, sentencias[1].end), (6, 6));
+}
+
+#[test]
+fn test_parse_cell_recupera_tras_error_de_sintaxis() {
+    // Dado: una celda con una función rota entre dos válidas
+    let lines = vec![
+        String::from("import os"),
+        String::from("def buena():"),
+        String::from("    return 1"),
+        String::from("def rota(:"),
+        String::from("    pass"),
+        String::from("def otra():"),
+        String::from("    return 2"),
+    ];
+
+    // Cuando: analizamos la celda
+    let sentencias = parse_cell(&lines);
+
+    // Entonces: se conservan las sentencias válidas con sus líneas originales
+    let rangos: Vec<(usize, usize)> = sentencias.iter().map(|s| (s.start, s.end)).collect();
+    assert_eq!(rangos, vec![(0, 0), (1, 2), (5, 6)]);
+}
+
+#[test]
+fn test_parse_cell_bloque_incompleto_antes_del_error() {
+    // Dado: una función con un error de sintaxis dentro de su cuerpo
+    let lines = vec![
+        String::from("def f():"),
+        String::from("    x = 1"),
+        String::from("    y = (("),
+    ];
+
+    // Cuando: analizamos la celda
+    let sentencias = parse_cell(&lines);
+
+    // Entonces: no se devuelve una versión recortada de la función
+    assert!(sentencias.is_empty());
+}
+
+#[test]
+fn test_transform_traduce_magics_y_escapes() {
+    // Dado: líneas con magics, escapes de shell y ayuda
+    let lines: Vec<String> = [
+        "%matplotlib inline",
+        "!pip install requests",
+        "    archivos = !ls -la",
+        "salida = !!date",
+        "%time x = f()",
+        "obj?",
+        "x = 1",
+    ]
+    .iter()
+    .map(|l| l.to_string())
+    .collect();
+
+    // Cuando: las traducimos como IPython
+    let resultado = transform(&lines, MagicsMode::Translate);
+
+    // Entonces: son llamadas a get_ipython() con la misma indentación
+    assert_eq!(
+        resultado,
+        vec![
+            "get_ipython().run_line_magic('matplotlib', 'inline')",
+            "get_ipython().system('pip install requests')",
+            "    archivos = get_ipython().getoutput('ls -la')",
+            "salida = get_ipython().getoutput('date')",
+            "get_ipython().run_line_magic('time', 'x = f()')",
+            "get_ipython().run_line_magic('pinfo', 'obj')",
+            "x = 1",
+        ]
+    );
+}
+
+#[test]
+fn test_transform_strip_elimina_magics() {
+    // Dado: magics en una celda y dentro de una función
+    let lines: Vec<String> = [
+        "%load_ext autoreload",
+        "def f():",
+        "    !echo hola",
+        "    archivos = !ls",
+        "%timeit -n 10 -r 3 y = f()",
+    ]
+    .iter()
+    .map(|l| l.to_string())
+    .collect();
+
+    // Cuando: las eliminamos
+    let resultado = transform(&lines, MagicsMode::Strip);
+
+    // Entonces: quedan sentencias de Python que no dependen de IPython
+    assert_eq!(
+        resultado,
+        vec![
+            "pass",
+            "def f():",
+            "    pass",
+            "    archivos = None",
+            "y = f()"
+        ]
+    );
+}
+
+#[test]
+fn test_transform_respeta_strings_y_continuaciones() {
+    // Dado: un `%` que continúa una expresión y un `!` dentro de un docstring
+    let lines: Vec<String> = [
+        "texto = (\"%d\"",
+        "    % n)",
+        "doc = \"\"\"",
+        "!no es un comando",
+        "\"\"\"",
+        "!echo \\",
+        "  hola",
+        "%%bash",
+    ]
+    .iter()
+    .map(|l| l.to_string())
+    .collect();
+
+    // Cuando: las traducimos
+    let resultado = transform(&lines, MagicsMode::Translate);
+
+    // Entonces: solo cambia el escape real; su continuación queda vacía
+    assert_eq!(resultado[..5], lines[..5]);
+    assert_eq!(resultado[5], "get_ipython().system('echo hola')");
+    assert_eq!(resultado[6], "");
+    assert_eq!(resultado[7], "%%bash");
+}
+
+#[test]
+fn test_transform_ignora_parentesis_y_almohadillas_en_strings() {
+    // Dado: paréntesis y `#` dentro de strings simples antes de un escape
+    let lines: Vec<String> = [
+        r#"pat = re.compile(r"\(")"#,
+        r#"etiqueta = '# [' + "\"{" "#,
+        "def f():",
+        "    files = !ls",
+    ]
+    .iter()
+    .map(|l| l.to_string())
+    .collect();
+
+    // Cuando: las traducimos
+    let resultado = transform(&lines, MagicsMode::Translate);
+
+    // Entonces: los strings no abren paréntesis y el escape se traduce
+    assert_eq!(resultado[..3], lines[..3]);
+    assert_eq!(resultado[3], "    files = get_ipython().getoutput('ls')");
+}
+
+#[test]
+fn test_escape_tras_parentesis_en_string_no_pierde_la_funcion() {
+    // Dado: una celda con un regex con paréntesis y una función con un escape
+    let raw = r#"{"nbformat": 4, "nbformat_minor": 5, "cells": [
+        {"cell_type": "code", "metadata": {}, "source": "import re\npat = re.compile(r\"\\(\")\n\n\ndef f():\n    files = !ls\n    return files\n"}
+    ]}"#;
+
+    // Cuando: lo cargamos
+    let jf = JupyterFunctions::from_string(raw, "ipynb", None).unwrap();
+
+    // Entonces: la función se extrae
+    assert_eq!(jf.functions_names(), vec!["f"]);
+}
+
+#[test]
+fn test_cell_magic_clasifica_magics_de_celda() {
+    // Dado: primeras líneas de celdas con distintas magics
+    let celda = |texto: &str| -> Vec<String> { texto.lines().map(String::from).collect() };
+
+    // Cuando: detectamos la magic de cada celda
+    // Entonces: se distingue Python, writefile y otros lenguajes
+    assert_eq!(
+        cell_magic(&celda("\n%%time\nx = 1")),
+        Some(CellMagic::Python { line: 1 })
+    );
+    assert_eq!(
+        cell_magic(&celda("%%writefile -a pkg/utils.py\nx = 1")),
+        Some(CellMagic::WriteFile {
+            line: 0,
+            path: String::from("pkg/utils.py"),
+            append: true
+        })
+    );
+    assert_eq!(
+        cell_magic(&celda("%%bash\nls")),
+        Some(CellMagic::Foreign(String::from("bash")))
+    );
+    assert_eq!(cell_magic(&celda("x = 1\n%%bash")), None);
+}
+
+#[test]
+fn test_module_name_desde_ruta() {
+    // Dado: rutas de archivos escritos con %%writefile
+    // Cuando: calculamos el nombre de módulo
+    // Entonces: solo los .py con nombres válidos son módulos
+    assert_eq!(module_name("utils.py"), Some(String::from("utils")));
+    assert_eq!(
+        module_name("./pkg/utils.py"),
+        Some(String::from("pkg.utils"))
+    );
+    assert_eq!(module_name("config.yaml"), None);
+    assert_eq!(module_name("mi-modulo.py"), None);
+}
+
+#[test]
+fn test_directivas_nbdev_al_principio_de_la_celda() {
+    // Dado: una celda con directivas seguidas de código y de un comentario `#|`
+    let lines: Vec<String> = ["", "#| export", "#|default_exp core", "x = 1", "#| hide"]
+        .iter()
+        .map(|l| l.to_string())
+        .collect();
+
+    // Cuando: leemos sus directivas
+    let resultado = directives(&lines);
+
+    // Entonces: solo cuentan las de antes del código
+    assert_eq!(
+        resultado,
+        vec![
+            (String::from("export"), String::new()),
+            (String::from("default_exp"), String::from("core")),
+        ]
+    );
+    assert!(is_exported(&resultado));
+    assert_eq!(default_exp(&resultado), Some(String::from("core")));
+}
+
+#[test]
+fn test_celda_hide_no_se_exporta() {
+    // Dado: una celda oculta y otra sin directivas
+    let oculta = vec![String::from("#| hide"), String::from("x = 1")];
+    let normal = vec![String::from("# export"), String::from("x = 1")];
+
+    // Cuando / Entonces: ninguna se exporta
+    assert!(!is_exported(&directives(&oculta)));
+    assert!(!is_exported(&directives(&normal)));
+}
+
+#[test]
+fn test_percent_lee_etiquetas_del_marcador() {
+    // Dado: un script percent con etiquetas en notación de jupytext
+    let raw = "# %% tags=[\"export\", \"test\"]\nx = 1\n\n# %%\ny = 2\n";
+
+    // Cuando: lo parseamos
+    let notebook = Notebook::parse(raw, NotebookFormat::Percent).unwrap();
+
+    // Entonces: la primera celda tiene las etiquetas y la segunda ninguna
+    assert_eq!(notebook.cells[0].metadata.tags, vec!["export", "test"]);
+    assert!(notebook.cells[1].metadata.tags.is_empty());
+}
+
+#[test]
+fn test_notebook_lee_etiquetas_de_metadata() {
+    // Dado: un notebook con etiquetas en los metadatos de una celda
+    let raw = r#"{"nbformat": 4, "nbformat_minor": 5, "cells": [
+        {"cell_type": "code", "metadata": {"tags": ["scratch"], "collapsed": true}, "source": "x = 1"},
+        {"cell_type": "code", "metadata": {}, "source": "y = 2"}
+    ]}"#;
+
+    // Cuando: lo parseamos
+    let notebook = Notebook::from_json(raw).unwrap();
+
+    // Entonces: las etiquetas están disponibles y el resto de metadatos se ignora
+    assert_eq!(notebook.cells[0].metadata.tags, vec!["scratch"]);
+    assert!(notebook.cells[1].metadata.tags.is_empty());
+}
+
+#[test]
+fn test_headings_ignora_bloques_de_codigo() {
+    // Dado: un markdown con encabezados, cierre de almohadillas y un bloque de código
+    let texto = "# Datos\n\n~~~\n# comentario\n~~~\n\n### Limpieza ##\n#sin_espacio\n";
+
+    // Cuando: extraemos los encabezados
+    let encontrados = headings(texto);
+
+    // Entonces: solo cuentan los encabezados ATX fuera del bloque
+    assert_eq!(
+        encontrados,
+        vec![(1, String::from("Datos")), (3, String::from("Limpieza"))]
+    );
+}
+
+#[test]
+fn test_contexts_apila_encabezados_por_nivel() {
+    // Dado: encabezados de niveles 1, 3, 2 y 1
+    let raw = r####"{"nbformat": 4, "nbformat_minor": 5, "cells": [
+        {"cell_type": "markdown", "metadata": {}, "source": "# A"},
+        {"cell_type": "markdown", "metadata": {}, "source": "### B"},
+        {"cell_type": "code", "metadata": {}, "source": "x = 1"},
+        {"cell_type": "markdown", "metadata": {}, "source": "## C"},
+        {"cell_type": "code", "metadata": {}, "source": "y = 2"},
+        {"cell_type": "markdown", "metadata": {}, "source": "# D"},
+        {"cell_type": "code", "metadata": {}, "source": "z = 3"}
+    ]}"####;
+    let notebook = Notebook::from_json(raw).unwrap();
+
+    // Cuando: calculamos el contexto de cada celda
+    let contextos = contexts(&notebook);
+
+    // Entonces: un encabezado cierra los de nivel igual o mayor
+    assert_eq!(contextos.len(), 7);
+    assert!(contextos[0].headings.is_empty());
+    assert_eq!(contextos[2].section(), "A > B");
+    assert_eq!(contextos[4].section(), "A > C");
+    assert_eq!(contextos[6].section(), "D");
+    assert_eq!(contextos[6].markdown.as_deref(), Some("# D"));
+    assert!(contextos[4].in_section("A > C"));
+    assert!(!contextos[4].in_section("A > B"));
+}
+
+#[test]
+fn test_execution_count_y_prompt_number() {
+    // Dado: una celda v4 con execution_count y una v3 con prompt_number
+    let v4 = r#"{"nbformat": 4, "nbformat_minor": 5, "cells": [
+        {"cell_type": "code", "execution_count": 7, "metadata": {}, "source": "x = 1"},
+        {"cell_type": "code", "execution_count": null, "metadata": {}, "source": "y = 2"}
+    ]}"#;
+    let v3 = r#"{"nbformat": 3, "nbformat_minor": 0, "worksheets": [{"cells": [
+        {"cell_type": "code", "prompt_number": 4, "input": "x = 1", "language": "python"}
+    ]}]}"#;
+
+    // Cuando: los parseamos
+    let notebook_v4 = Notebook::from_json(v4).unwrap();
+    let notebook_v3 = Notebook::from_json(v3).unwrap();
+
+    // Entonces: el contador está disponible en los dos esquemas
+    assert_eq!(notebook_v4.cells[0].execution_count, Some(7));
+    assert_eq!(notebook_v4.cells[1].execution_count, None);
+    assert_eq!(notebook_v3.cells[0].execution_count, Some(4));
+}
+
+#[test]
+fn test_resolution_from_name() {
+    // Dado / Cuando / Entonces: los nombres de la API de Python se interpretan sin mayúsculas
+    assert_eq!(Resolution::from_name("last"), Some(Resolution::Last));
+    assert_eq!(
+        Resolution::from_name("Execution_Count"),
+        Some(Resolution::ExecutionCount)
+    );
+    assert_eq!(Resolution::from_name("first"), None);
+}
+
+#[test]
+fn test_parse_cell_import_protegido_solo_con_imports() {
+    // Dado: bloques `if`/`try` con solo imports y alias, y otros con más código
+    let lines: Vec<String> = [
+        "if USE_FAST and sys.version_info > (3,):",
+        "    import ujson as json",
+        "elif OTRO:",
+        "    pass",
+        "else:",
+        "    json = None",
+        "try:",
+        "    import math",
+        "    print('x')",
+        "except ImportError:",
+        "    pass",
+        "if DEBUG:",
+        "    def dbg():",
+        "        import pdb",
+    ]
+    .iter()
+    .map(|l| l.to_string())
+    .collect();
+
+    // Cuando: analizamos la celda
+    let kinds: Vec<StatementKind> = parse_cell(&lines).into_iter().map(|s| s.kind).collect();
+
+    // Entonces: solo el primero es un import protegido, con los nombres de sus condiciones
+    assert_eq!(
+        kinds,
+        vec![
+            StatementKind::GuardedImport {
+                loaded: vec![
+                    String::from("USE_FAST"),
+                    String::from("sys"),
+                    String::from("OTRO")
+                ]
+            },
+            StatementKind::Other,
+            StatementKind::Other,
+        ]
+    );
+}
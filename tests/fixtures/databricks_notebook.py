@@ -0,0 +1,27 @@
+# Databricks notebook source
+import re
+from datetime import date
+
+# COMMAND ----------
+
+# MAGIC %md
+# MAGIC ## Limpieza de texto
+# MAGIC
+# MAGIC def no_es_codigo():
+
+# COMMAND ----------
+
+# DBTITLE 1,Normalización
+def normalizar(texto):
+    return re.sub(r"\s+", " ", texto).strip().lower()
+
+# COMMAND ----------
+
+# MAGIC %sql
+# MAGIC SELECT * FROM ventas
+
+# COMMAND ----------
+
+# MAGIC %python
+# MAGIC def anio_actual():
+# MAGIC     return date.today().year
@@ -0,0 +1,54 @@
+import marimo
+
+__generated_with = "0.9.14"
+app = marimo.App(width="medium")
+
+with app.setup:
+    import math
+    from collections import Counter
+
+
+@app.cell(hide_code=True)
+def _(mo):
+    mo.md(
+        r"""
+        # Utilidades
+
+        def no_es_codigo():
+        """
+    )
+    return
+
+
+@app.cell
+def _():
+    import marimo as mo
+    return (mo,)
+
+
+@app.cell
+def _(math):
+    def area_circulo(r):
+        return math.pi * r ** 2
+
+    def perimetro_circulo(r):
+        return 2 * math.pi * r
+    return (
+        area_circulo,
+        perimetro_circulo,
+    )
+
+
+@app.function
+def mas_comun(xs):
+    return Counter(xs).most_common(1)[0][0]
+
+
+@app.cell
+def _(area_circulo):
+    area_circulo(2)
+    return
+
+
+if __name__ == "__main__":
+    app.run()
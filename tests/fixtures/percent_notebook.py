@@ -0,0 +1,34 @@
+# ---
+# jupyter:
+#   jupytext:
+#     formats: ipynb,py:percent
+#     text_representation:
+#       extension: .py
+#       format_name: percent
+#       format_version: '1.3'
+#   kernelspec:
+#     display_name: Python 3
+#     language: python
+#     name: python3
+# ---
+
+# %%
+import math
+from collections import Counter
+
+# %% [markdown]
+# ## Geometría
+#
+# def no_es_codigo():
+
+# %%
+def area_circulo(r):
+    return math.pi * r ** 2
+
+
+def perimetro_circulo(r):
+    return 2 * math.pi * r
+
+# %% Conteos
+def mas_comun(xs):
+    return Counter(xs).most_common(1)[0][0]